@@ -1,15 +1,12 @@
-use log::{debug, error, info};
-use ring::digest::{Context, SHA256};
-use std::env;
+use log::{debug, info};
 use std::fs;
-use std::io::{BufReader, Read, Result as SimpleResult};
 use std::path::{Path, PathBuf};
 use std::result::Result;
 use structopt::StructOpt;
-use thiserror::Error;
 use uuid::Uuid;
 
 use crate::common;
+use crate::error::Error;
 
 #[derive(Debug, PartialEq, StructOpt)]
 pub struct Args {
@@ -18,167 +15,195 @@ pub struct Args {
 
     #[structopt(short, long)]
     name: String,
-}
-
-#[derive(Error, Debug)]
-enum AddCommandError {
-    #[error("reading .rpilot file failed. Make sure this project is initialised properly")]
-    NotInitialized,
-
-    #[error("The specified profile name already exists for this project. Please specify different name. If you want to modify the content of the existing env, then please use edit command.")]
-    AlreadyExists,
-
-    #[error("There was no valid env variables in the file")]
-    NoValidEnv,
-
-    #[error("Failed at saving a new rpilot entry ")]
-    SaveFileError,
-
-    #[error("Failed at reading the config")]
-    ConfigReadError,
 
-    #[error("external library failed")]
-    ExternalFail(#[from] std::io::Error),
+    /// Profile to inherit the variables from. Can be repeated, and later bases win.
+    #[structopt(long = "base")]
+    bases: Vec<String>,
 }
 
-pub fn execute(args: &Args) {
-    match _execute(args) {
-        Ok(_) => {
-            info!("Successfully created a new rpilot entry");
-        }
-        Err(e) => {
-            error!("{}", e);
-        }
+pub fn execute(args: &Args) -> Result<(), Error> {
+    let ws = common::open_workspace()?;
+    let cipher = common::load_cipher(&ws.project_dir, &ws.project_id).map_err(Error::Encryption)?;
+    read_config(&ws, args, cipher.as_ref())?;
+
+    // The editor runs without the lock, so that other commands can run meanwhile
+    let env = match &args.source {
+        Some(v) => fs::read_to_string(v)?,
+        None => generate_new_env()?,
+    };
+    common::EnvFile::parse(&env)?;
+
+    let _lock = common::lock_project(&ws.project_dir, &ws.project_id)?;
+    let (config, mut project) = read_config(&ws, args, cipher.as_ref())?;
+    insert_profile(
+        &ws.project_dir,
+        &ws.project_id,
+        &mut project,
+        &args.name,
+        &env,
+        cipher.as_ref(),
+    )?;
+    if !args.bases.is_empty() {
+        set_bases(&mut project, &args.name, &args.bases)?;
     }
+    common::save_config(&project, &config, cipher.as_ref())?;
+    info!("Successfully created a new rpilot entry");
+    Ok(())
 }
 
-fn _execute(args: &Args) -> Result<(), AddCommandError> {
-    let pwd = env::current_dir()?;
-    let project_dir = common::get_data_dir()?;
-
-    let project_id = common::get_project_id(&pwd);
-
-    if project_id.is_none() {
-        return Err(AddCommandError::NotInitialized);
+/// Reads the config, and checks that the profile does not exist yet and that its bases do.
+/// It is checked before the editor is opened, and again once the project is locked.
+fn read_config(
+    ws: &common::Workspace,
+    args: &Args,
+    cipher: Option<&common::Cipher>,
+) -> Result<(PathBuf, common::Project), Error> {
+    let (config, project) = common::read_config(&ws.project_dir, &ws.project_id, cipher)
+        .map_err(Error::CorruptConfig)?;
+    if check_if_profile_exists(&project.entries, &args.name) {
+        return Err(Error::AlreadyExists);
     }
+    // Checked before the profile is written so that no file is left behind
+    base_ids(&project, &args.bases)?;
+    Ok((config, project))
+}
 
-    let project_id = project_id.unwrap();
-    info!("Retrieved proejct id is {}", project_id);
-
-    let (mut config, mut project) = common::read_config(&project_dir, &project_id)
-        .map_err(|_| AddCommandError::ConfigReadError)?;
-
-    let exists = check_if_profile_exists(&project.entries, &args.name);
-    if exists {
-        return Err(AddCommandError::AlreadyExists);
+/// Stores `env` as a new profile of the project. The config has to be saved by the caller.
+pub(crate) fn insert_profile(
+    project_dir: &Path,
+    project_id: &str,
+    project: &mut common::Project,
+    name: &str,
+    env: &str,
+    cipher: Option<&common::Cipher>,
+) -> Result<(), Error> {
+    if check_if_profile_exists(&project.entries, name) {
+        return Err(Error::AlreadyExists);
     }
 
-    let (env_path, env_id) = get_env_internal_path(&project_dir, &project_id);
-
-    match &args.source {
-        Some(v) => {
-            copy_env(&v, &env_path)?;
-        }
-        None => {
-            generate_new_env_file(&env_path)?;
-        }
+    let (env_path, env_id) = get_env_internal_path(project_dir, project_id);
+    let entry = common::Entry {
+        name: String::from(name),
+        id: env_id,
+        ..Default::default()
     };
+    project.entries.push(entry);
+    common::write_profile(project_dir, project_id, project, name, env, cipher)?;
 
     info!(
         "{}",
         format!("Copied the env file to {}", env_path.to_str().unwrap())
     );
+    Ok(())
+}
 
-    let hash = generate_file_hash(&env_path)?;
-    let entry = common::Entry {
-        hash,
-        name: String::from(&args.name),
-        id: env_id,
-    };
-
-    project.entries.push(entry);
-    common::save_config(&project, &mut config).map_err(|_| AddCommandError::SaveFileError)?;
+/// Makes the profile `name` inherit from the profiles `bases`. The config has to be saved by
+/// the caller.
+///
+/// # Errors
+///
+/// Will return `Err` if any of the profiles does not exist, or if the bases would form a cycle
+pub(crate) fn set_bases(
+    project: &mut common::Project,
+    name: &str,
+    bases: &[String],
+) -> Result<(), Error> {
+    let ids = base_ids(project, bases)?;
+    let index = project
+        .entries
+        .iter()
+        .position(|entry| entry.name == name)
+        .ok_or(Error::NotExists)?;
+
+    let previous = std::mem::replace(&mut project.entries[index].bases, ids);
+    let id = project.entries[index].id.clone();
+    if let Err(e) = common::linearize(project, &id) {
+        project.entries[index].bases = previous;
+        return Err(e);
+    }
     Ok(())
 }
 
+fn base_ids(project: &common::Project, bases: &[String]) -> Result<Vec<String>, Error> {
+    bases
+        .iter()
+        .map(|base| common::select_profile(project, base).map(|v| v.id.clone()))
+        .collect::<Result<Vec<String>, _>>()
+        .map_err(|_| Error::NotExists)
+}
+
 fn check_if_profile_exists(entries: &[common::Entry], name: &str) -> bool {
     debug!("Checking whether the same profile name already exists in the entries");
-    return entries.iter().any(|entry| entry.name == name);
+    entries.iter().any(|entry| entry.name == name)
 }
 
 fn get_env_internal_path(project_dir: &Path, id: &str) -> (PathBuf, String) {
     debug!("Retrieving the new path to copy this env file to");
 
     let env_id = Uuid::new_v4().to_string();
-    let data_dir = project_dir.join(id.to_string());
+    let data_dir = project_dir.join(id);
     let env_path = data_dir.join(&env_id);
     (env_path, env_id)
 }
 
-fn copy_env(source: &Path, env_path: &Path) -> SimpleResult<u64> {
-    fs::copy(source, env_path)
-}
-
-fn generate_new_env_file(env_path: &Path) -> Result<(), AddCommandError> {
+fn generate_new_env() -> Result<String, Error> {
     let template = "# Please add new env values";
     let edited = edit::edit(template)?;
 
     debug!("Edited values: {}", edited);
     if edited == template {
-        return Err(AddCommandError::NoValidEnv);
+        return Err(Error::NoValidEnv);
     }
-    fs::write(env_path, edited)?;
-    Ok(())
-}
-
-fn generate_file_hash(path: &Path) -> Result<String, AddCommandError> {
-    let input = fs::File::open(path)?;
-    let mut reader = BufReader::new(input);
-
-    let mut context = Context::new(&SHA256);
-    let mut buffer = [0; 1024];
-
-    loop {
-        let count = reader.read(&mut buffer)?;
-        if count == 0 {
-            break;
-        }
-        context.update(&buffer[..count]);
-    }
-
-    let digest = context.finish();
-    let hash_bytes = digest.as_ref();
-    let encoded_hash = base64::encode(hash_bytes);
-    Ok(encoded_hash)
+    Ok(edited)
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
     use std::fs;
-    use std::io::prelude::*;
     use tempdir::TempDir;
 
     #[test]
-    fn test_copy_env() {
-        let tmp_dir = TempDir::new("test_copy_env").unwrap();
-        let mut tmp_dir_path = tmp_dir.path().to_owned();
-        tmp_dir_path.push(".env");
-        fs::File::create(&tmp_dir_path).unwrap();
-
-        let tmp_dest_dir = TempDir::new("test_copy_env_dest").unwrap();
-        let mut tmp_dest_path = tmp_dest_dir.path().to_owned();
-        tmp_dest_path.push("test");
-        assert_eq!(copy_env(&tmp_dir_path, &tmp_dest_path).is_ok(), true);
+    fn test_insert_profile() {
+        let tmp_dir = TempDir::new("test_insert_profile").unwrap();
+        let tmp_dir_path = tmp_dir.path().to_owned();
+        fs::create_dir(tmp_dir_path.join("project")).unwrap();
+        let mut project = common::Project::default();
+
+        insert_profile(&tmp_dir_path, "project", &mut project, "dev", "A=1", None).unwrap();
+        assert_eq!(project.entries.len(), 1);
+        assert_eq!(project.entries[0].name, "dev");
+
+        let entry = &project.entries[0];
+        let (_, env) = common::read_env(&tmp_dir_path, "project", &project, entry, None).unwrap();
+        assert_eq!(env, Some("A=1".to_string()));
+        assert_eq!(entry.hash, common::hash_content(b"A=1"));
+
+        let result = insert_profile(&tmp_dir_path, "project", &mut project, "dev", "A=2", None);
+        assert!(matches!(result, Err(Error::AlreadyExists)));
     }
 
     #[test]
-    fn test_generate_file_hash() {
-        let tmp_dir = TempDir::new("test_generate_file_hash").unwrap();
-        let mut tmp_dir_path = tmp_dir.path().to_owned();
-        tmp_dir_path.push(".env");
-        let mut tmp_file = fs::File::create(&tmp_dir_path).unwrap();
-        tmp_file.write_all(b"ENV=test").unwrap();
-        assert_eq!(generate_file_hash(&tmp_dir_path).is_ok(), true);
+    fn test_set_bases_rejects_cycles() {
+        let tmp_dir = TempDir::new("test_set_bases").unwrap();
+        let tmp_dir_path = tmp_dir.path().to_owned();
+        fs::create_dir(tmp_dir_path.join("project")).unwrap();
+        let mut project = common::Project::default();
+        for name in &["common", "dev"] {
+            insert_profile(&tmp_dir_path, "project", &mut project, name, "A=1", None).unwrap();
+        }
+
+        set_bases(&mut project, "dev", &["common".to_string()]).unwrap();
+        assert_eq!(
+            project.entries[1].bases,
+            vec![project.entries[0].id.clone()]
+        );
+
+        let result = set_bases(&mut project, "common", &["dev".to_string()]);
+        assert!(matches!(result, Err(Error::InheritanceCycle(_))));
+        assert!(project.entries[0].bases.is_empty());
+
+        let result = set_bases(&mut project, "dev", &["missing".to_string()]);
+        assert!(matches!(result, Err(Error::NotExists)));
     }
 }
@@ -0,0 +1,84 @@
+use log::info;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::result::Result;
+use structopt::StructOpt;
+
+use crate::common;
+use crate::error::Error;
+
+/// File in the data dir which lists the projects the shell hook may load, one
+/// `<project id> <path>` per line
+pub const ALLOWED_FILENAME: &str = "allowed";
+
+#[derive(Debug, PartialEq, StructOpt)]
+pub struct Args {
+    /// Stop loading this project from the shell hook
+    #[structopt(long)]
+    revoke: bool,
+}
+
+pub fn execute(args: &Args) -> Result<(), Error> {
+    let common::Workspace {
+        pwd,
+        project_dir,
+        project_id,
+    } = common::open_workspace()?;
+
+    fs::create_dir_all(&project_dir)?;
+    let _lock = common::lock_data_dir(&project_dir)?;
+    let allowed_path = project_dir.join(ALLOWED_FILENAME);
+    let mut allowed = read_allowed(&allowed_path)?;
+    let line = allowed_line(&project_id, &pwd);
+    allowed.retain(|v| *v != line);
+    if !args.revoke {
+        allowed.push(line);
+    }
+    common::write_atomic(&allowed_path, allowed.join("").as_bytes())?;
+
+    if args.revoke {
+        info!("The shell hook will not load {} anymore", pwd.display());
+    } else {
+        info!("The shell hook will load {}", pwd.display());
+    }
+    Ok(())
+}
+
+/// Returns whether the project `id` checked out at `dir` was allowed. Moving the
+/// checkout, or putting another project at the same path, revokes it.
+pub(crate) fn is_allowed(project_dir: &Path, id: &str, dir: &Path) -> Result<bool, Error> {
+    let line = allowed_line(id, dir);
+    Ok(read_allowed(&project_dir.join(ALLOWED_FILENAME))?.contains(&line))
+}
+
+fn allowed_line(id: &str, dir: &Path) -> String {
+    format!("{} {}\n", id, dir.display())
+}
+
+fn read_allowed(path: &Path) -> Result<Vec<String>, Error> {
+    match fs::read_to_string(path) {
+        Ok(v) => Ok(v.lines().map(|line| format!("{}\n", line)).collect()),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_is_allowed() {
+        let tmp_dir = TempDir::new("test_is_allowed").unwrap();
+        let tmp_dir_path = tmp_dir.path().to_owned();
+        let dir = Path::new("/src/project");
+        assert!(!is_allowed(&tmp_dir_path, "id", dir).unwrap());
+
+        fs::write(tmp_dir_path.join(ALLOWED_FILENAME), allowed_line("id", dir)).unwrap();
+        assert!(is_allowed(&tmp_dir_path, "id", dir).unwrap());
+        assert!(!is_allowed(&tmp_dir_path, "other", dir).unwrap());
+        assert!(!is_allowed(&tmp_dir_path, "id", Path::new("/src/moved")).unwrap());
+    }
+}
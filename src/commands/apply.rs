@@ -1,110 +1,371 @@
 use log::debug;
-use log::error;
 use log::info;
-use std::env;
+use log::warn;
 use std::fs;
-use std::io::{stdin, stdout, Result as SimpleResult, Write};
 use std::os::unix;
 use std::path::Path;
 use std::result::Result;
 use structopt::StructOpt;
-use thiserror::Error;
 
+use crate::commands::add;
+use crate::commands::check;
 use crate::common;
+use crate::error::Error;
 
 #[derive(Debug, PartialEq, StructOpt)]
 pub struct Args {
     #[structopt(short, long)]
     name: String,
+
+    /// Apply without asking for a confirmation. Protected profiles still ask for their name.
+    #[structopt(short, long)]
+    yes: bool,
+
+    /// How to put the profile at .env: symlink, copy or hardlink. Defaults to the apply-mode setting of the project.
+    #[structopt(short, long)]
+    mode: Option<common::ApplyMode>,
+
+    /// Apply the profile even when it does not match the schema of the project
+    #[structopt(long)]
+    no_check: bool,
 }
 
-#[derive(Error, Debug)]
-enum ApplyCommandError {
-    #[error("reading .rpilot file failed. Make sure this project is initialised properly.")]
-    NotInitialized,
+pub fn execute(args: &Args) -> Result<(), Error> {
+    let common::Workspace {
+        pwd,
+        project_dir,
+        project_id,
+    } = common::open_workspace()?;
+    let _lock = common::lock_project(&project_dir, &project_id)?;
+    let cipher = common::load_cipher(&project_dir, &project_id).map_err(Error::Encryption)?;
+    let (config_path, mut project) =
+        common::read_config(&project_dir, &project_id, cipher.as_ref())
+            .map_err(Error::CorruptConfig)?;
+
+    let profile = common::select_profile(&project, &args.name).map_err(|_| Error::NotExists)?;
 
-    #[error("the specified profile name does not exists for this project. Please make sure that you are passing the correvt name.")]
-    NotExists,
+    let env_path = project_dir.join(&project_id).join(&profile.id);
+    let env = common::materialize(
+        &project_dir,
+        &project_id,
+        &project,
+        &profile.id,
+        cipher.as_ref(),
+    )?;
 
-    #[error("the command was aborted")]
-    Aborted,
+    if !args.no_check {
+        check::ensure_valid(&pwd, &args.name, &env)?;
+    }
 
-    #[error("Failed at saving the selected profile")]
-    SaveFileError,
+    let copy_only = if cipher.is_some() {
+        Some("the profiles of this project are encrypted")
+    } else if !profile.bases.is_empty() {
+        Some("the profile inherits from other profiles")
+    } else if project.interpolation != common::Interpolation::Off {
+        Some("the profiles of this project are interpolated")
+    } else {
+        None
+    };
+    let mode = select_mode(args, &project, copy_only)?;
+    confirm_apply(args, &project, profile, mode)?;
+    protect_existing_env(
+        args,
+        &pwd,
+        &project_dir,
+        &project_id,
+        &mut project,
+        cipher.as_ref(),
+    )?;
 
-    #[error("Failed at reading the config")]
-    ConfigReadError,
+    let current_env_path = pwd.join(".env");
+    match mode {
+        common::ApplyMode::Symlink => common::replace_with(&current_env_path, |tmp_path| {
+            unix::fs::symlink(&env_path, tmp_path)
+        })?,
+        common::ApplyMode::Hardlink => common::replace_with(&current_env_path, |tmp_path| {
+            fs::hard_link(&env_path, tmp_path)
+        })?,
+        common::ApplyMode::Copy => common::write_private_file(&current_env_path, &env)?,
+    }
+    *project.current_profile = Some(String::from(&args.name));
+    project.applied_hash = match mode {
+        common::ApplyMode::Copy => common::hash_content(env.as_bytes()),
+        _ => String::new(),
+    };
 
-    #[error("external library failed")]
-    ExternalFail(#[from] std::io::Error),
+    common::save_config(&project, &config_path, cipher.as_ref())?;
+    info!("successfully updated the current .env file");
+    Ok(())
 }
 
-pub fn execute(args: &Args) {
-    match _execute(args) {
-        Ok(_) => info!("successfully updated the current .env file"),
-        Err(e) => {
-            error!("{}", e);
-        }
+/// Rewrites the `.env` which was applied as a copy after the current profile or one of its
+/// bases was changed. A copy which was edited after it was applied is left alone. The config
+/// has to be saved by the caller.
+pub(crate) fn refresh_applied_copy(
+    pwd: &Path,
+    project_dir: &Path,
+    project_id: &str,
+    project: &mut common::Project,
+    cipher: Option<&common::Cipher>,
+) -> Result<(), Error> {
+    if project.applied_hash.is_empty() {
+        return Ok(());
     }
+    let profile = match (*project.current_profile)
+        .as_deref()
+        .map(|v| common::select_profile(project, v))
+    {
+        Some(Ok(v)) => v,
+        _ => return Ok(()),
+    };
+    let current_env_path = pwd.join(".env");
+    let current_env = match fs::read(&current_env_path) {
+        Ok(v) => v,
+        Err(_) => return Ok(()),
+    };
+    if common::hash_content(&current_env) != project.applied_hash {
+        warn!("The current .env was modified after it was applied, so it was not updated");
+        return Ok(());
+    }
+
+    let env = common::materialize(project_dir, project_id, project, &profile.id, cipher)?;
+    if env.as_bytes() == &current_env[..] {
+        return Ok(());
+    }
+    common::write_private_file(&current_env_path, &env)?;
+    project.applied_hash = common::hash_content(env.as_bytes());
+    Ok(())
 }
 
-fn _execute(args: &Args) -> Result<(), ApplyCommandError> {
-    let pwd = env::current_dir()?;
-    let project_dir = common::get_data_dir()?;
-    let project_id = common::get_project_id(&pwd);
+/// Profiles which can not be linked as they are stored, because they are encrypted, inherit
+/// from other profiles or are interpolated, are always applied as a copy. `copy_only` tells why.
+fn select_mode(
+    args: &Args,
+    project: &common::Project,
+    copy_only: Option<&str>,
+) -> Result<common::ApplyMode, Error> {
+    match (args.mode, copy_only) {
+        (Some(common::ApplyMode::Copy), Some(_)) | (None, Some(_)) => Ok(common::ApplyMode::Copy),
+        (Some(mode), Some(reason)) => Err(Error::InvalidArgument(format!(
+            "{} and can not be applied with {} mode. Please use copy mode.",
+            reason, mode
+        ))),
+        (Some(mode), None) => Ok(mode),
+        (None, None) => Ok(project.apply_mode),
+    }
+}
 
-    if project_id.is_none() {
-        return Err(ApplyCommandError::NotInitialized);
+fn confirm_apply(
+    args: &Args,
+    project: &common::Project,
+    profile: &common::Entry,
+    mode: common::ApplyMode,
+) -> Result<(), Error> {
+    if profile.protected {
+        return confirm_protected_profile(&profile.name);
+    }
+    if args.yes || project.auto_confirm {
+        debug!("Skipping the confirmation");
+        return Ok(());
+    }
+    if !common::is_interactive() {
+        return Err(Error::NonInteractive(
+            "applying a profile needs a confirmation. Please pass --yes to apply it from a script."
+                .to_string(),
+        ));
     }
 
-    let project_id = project_id.unwrap();
-    let (mut config_path, mut project) = common::read_config(&project_dir, &project_id)
-        .map_err(|_| ApplyCommandError::ConfigReadError)?;
+    if should_apply_env(mode)? {
+        Ok(())
+    } else {
+        Err(Error::Aborted)
+    }
+}
 
-    let profile =
-        common::select_profile(&project, &args.name).map_err(|_| ApplyCommandError::NotExists)?;
+fn confirm_protected_profile(name: &str) -> Result<(), Error> {
+    if !common::is_interactive() {
+        return Err(Error::NonInteractive(format!(
+            "the profile {} is protected and can only be applied from a terminal.",
+            name
+        )));
+    }
 
-    let (env_path, _) = common::read_env(&project_dir, &project_id, &profile.id);
+    let message = format!(
+        "The profile {} is protected. Please type its name to apply it: ",
+        name
+    );
+    match common::prompt(&message)? {
+        Some(v) if v == name => Ok(()),
+        _ => Err(Error::Aborted),
+    }
+}
 
-    let should_apply = should_apply_env()?;
+enum ExistingEnvAction {
+    Import(String),
+    Backup,
+}
 
-    if should_apply {
-        set_symlink(&pwd, &env_path)?;
-        project.current_profile = Box::new(Some(String::from(&args.name)));
+/// Makes sure that a `.env` which is a real file, and not the profile which is currently
+/// applied, is imported as a new profile or backed up before it gets replaced
+fn protect_existing_env(
+    args: &Args,
+    pwd: &Path,
+    project_dir: &Path,
+    project_id: &str,
+    project: &mut common::Project,
+    cipher: Option<&common::Cipher>,
+) -> Result<(), Error> {
+    let current_env_path = pwd.join(".env");
+    let is_file = fs::symlink_metadata(&current_env_path)
+        .map(|m| m.file_type().is_file())
+        .unwrap_or(false);
+    if !is_file {
+        return Ok(());
+    }
 
-        return common::save_config(&project, &mut config_path)
-            .map_err(|_| ApplyCommandError::SaveFileError);
+    let current_env = fs::read_to_string(&current_env_path)?;
+    if is_current_profile(project_dir, project_id, project, &current_env, cipher)? {
+        return Ok(());
     }
-    Err(ApplyCommandError::Aborted)
+
+    let action = if args.yes || project.auto_confirm || !common::is_interactive() {
+        ExistingEnvAction::Backup
+    } else {
+        ask_existing_env_action()?
+    };
+
+    match action {
+        ExistingEnvAction::Import(name) => {
+            add::insert_profile(
+                project_dir,
+                project_id,
+                project,
+                &name,
+                &current_env,
+                cipher,
+            )?;
+            info!("Imported the current .env as {}", name);
+        }
+        ExistingEnvAction::Backup => {
+            let id = common::backup_env(project_dir, project_id, &current_env, cipher)?;
+            info!(
+                "Backed up the current .env as {}. Use restore-backup command to restore it.",
+                id
+            );
+        }
+    }
+    Ok(())
 }
 
-fn should_apply_env() -> Result<bool, ApplyCommandError> {
-    let mut buffer = String::new();
-    print!("This will create a symlink to .env file proceed if it is ok: [Y/N]");
-    stdout().flush()?;
-    let input = match stdin().read_line(&mut buffer) {
-        Ok(_) => Ok(buffer),
-        Err(_) => Err(ApplyCommandError::Aborted),
-    }?;
-
-    match input.as_str() {
-        "Y\n" | "y\n" => Ok(true),
-        v => {
-            print!("{}", v);
-            Ok(false)
+fn is_current_profile(
+    project_dir: &Path,
+    project_id: &str,
+    project: &common::Project,
+    current_env: &str,
+    cipher: Option<&common::Cipher>,
+) -> Result<bool, Error> {
+    let current = match project.current_profile.as_ref() {
+        Some(v) => v,
+        None => return Ok(false),
+    };
+    let profile = match common::select_profile(project, current) {
+        Ok(v) => v,
+        Err(_) => return Ok(false),
+    };
+    let env = common::materialize(project_dir, project_id, project, &profile.id, cipher)?;
+    Ok(env == current_env)
+}
+
+fn ask_existing_env_action() -> Result<ExistingEnvAction, Error> {
+    let message = "The current .env is not managed by rpilot. [i]mport it as a new profile, [b]ack it up or [a]bort: ";
+    match common::prompt(message)?.as_deref() {
+        Some("i") | Some("I") => match common::prompt("Name of the new profile: ")? {
+            Some(name) if !name.is_empty() => Ok(ExistingEnvAction::Import(name)),
+            _ => Err(Error::Aborted),
+        },
+        Some("b") | Some("B") => Ok(ExistingEnvAction::Backup),
+        _ => Err(Error::Aborted),
+    }
+}
+
+fn should_apply_env(mode: common::ApplyMode) -> Result<bool, Error> {
+    let message = match mode {
+        common::ApplyMode::Symlink => {
+            "This will create a symlink to .env file proceed if it is ok: [Y/N]"
+        }
+        common::ApplyMode::Hardlink => {
+            "This will create a hard link to .env file proceed if it is ok: [Y/N]"
         }
+        common::ApplyMode::Copy => {
+            "This will write a copy of the profile to .env file proceed if it is ok: [Y/N]"
+        }
+    };
+
+    match common::prompt(message)?.as_deref() {
+        Some("Y") | Some("y") => Ok(true),
+        _ => Ok(false),
     }
 }
 
-fn set_symlink(pwd: &Path, env_path: &Path) -> SimpleResult<()> {
-    let current_env_path = pwd.join(".env");
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempdir::TempDir;
 
-    if fs::remove_file(&current_env_path).is_err() {
-        debug!(".env file does not exist in the current directory")
+    fn args(mode: Option<common::ApplyMode>) -> Args {
+        Args {
+            name: "dev".to_string(),
+            yes: true,
+            mode,
+            no_check: false,
+        }
+    }
+
+    #[test]
+    fn test_select_mode() {
+        let project = common::Project {
+            apply_mode: common::ApplyMode::Hardlink,
+            ..Default::default()
+        };
+
+        let mode = select_mode(&args(None), &project, None).unwrap();
+        assert_eq!(mode, common::ApplyMode::Hardlink);
+        let mode = select_mode(&args(Some(common::ApplyMode::Symlink)), &project, None).unwrap();
+        assert_eq!(mode, common::ApplyMode::Symlink);
+
+        let mode = select_mode(&args(None), &project, Some("encrypted")).unwrap();
+        assert_eq!(mode, common::ApplyMode::Copy);
+        let result = select_mode(
+            &args(Some(common::ApplyMode::Symlink)),
+            &project,
+            Some("encrypted"),
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_refresh_applied_copy() {
+        let tmp_dir = TempDir::new("test_refresh_applied_copy").unwrap();
+        let tmp_dir_path = tmp_dir.path().to_owned();
+        fs::create_dir(tmp_dir_path.join("project")).unwrap();
+        let mut project = common::Project::default();
+        add::insert_profile(&tmp_dir_path, "project", &mut project, "dev", "A=1", None).unwrap();
+        let env_path = tmp_dir_path.join(".env");
+        fs::write(&env_path, "A=1").unwrap();
+        *project.current_profile = Some("dev".to_string());
+        project.applied_hash = common::hash_content(b"A=1");
+
+        let refresh = |project: &mut common::Project| {
+            refresh_applied_copy(&tmp_dir_path, &tmp_dir_path, "project", project, None)
+        };
+        common::write_profile(&tmp_dir_path, "project", &mut project, "dev", "A=2", None).unwrap();
+        refresh(&mut project).unwrap();
+        assert_eq!(fs::read_to_string(&env_path).unwrap(), "A=2");
+        assert_eq!(project.applied_hash, common::hash_content(b"A=2"));
+
+        fs::write(&env_path, "A=local").unwrap();
+        common::write_profile(&tmp_dir_path, "project", &mut project, "dev", "A=3", None).unwrap();
+        refresh(&mut project).unwrap();
+        assert_eq!(fs::read_to_string(&env_path).unwrap(), "A=local");
     }
-    unix::fs::symlink(env_path, &current_env_path)?;
-    let mut perms = fs::metadata(&current_env_path)?.permissions();
-    perms.set_readonly(true);
-    fs::set_permissions(&current_env_path, perms)?;
-    Ok(())
 }
@@ -0,0 +1,120 @@
+use log::{info, warn};
+use std::path::Path;
+use std::result::Result;
+use structopt::StructOpt;
+
+use crate::common;
+use crate::error::Error;
+
+#[derive(Debug, PartialEq, StructOpt)]
+pub struct Args {
+    /// Profile to check. Defaults to the current profile.
+    #[structopt(short, long, conflicts_with = "all")]
+    name: Option<String>,
+
+    /// Check every profile of the project
+    #[structopt(long)]
+    all: bool,
+
+    /// Treat keys which are not in the schema as errors
+    #[structopt(long)]
+    strict: bool,
+}
+
+pub fn execute(args: &Args) -> Result<(), Error> {
+    let common::Workspace {
+        pwd,
+        project_dir,
+        project_id,
+    } = common::open_workspace()?;
+    let schema = common::Schema::load(&pwd)?.ok_or_else(|| {
+        Error::InvalidArgument(format!(
+            "there is no {} or {} in this project",
+            common::SCHEMA_FILENAME,
+            common::EXAMPLE_FILENAME
+        ))
+    })?;
+    let cipher = common::load_cipher(&project_dir, &project_id).map_err(Error::Encryption)?;
+    let (_, project) = common::read_config(&project_dir, &project_id, cipher.as_ref())
+        .map_err(Error::CorruptConfig)?;
+
+    let names = if args.all {
+        project.entries.iter().map(|v| v.name.clone()).collect()
+    } else {
+        match args.name.as_ref().or((*project.current_profile).as_ref()) {
+            Some(v) => vec![v.clone()],
+            None => return Err(Error::NoProfileIsApplied),
+        }
+    };
+
+    let mut failed = Vec::new();
+    for name in &names {
+        let profile = common::select_profile(&project, name).map_err(|_| Error::NotExists)?;
+        let env = common::materialize(
+            &project_dir,
+            &project_id,
+            &project,
+            &profile.id,
+            cipher.as_ref(),
+        )?;
+        let issues = schema.check(&common::EnvFile::parse(&env)?);
+        if issues.is_empty() {
+            println!("* {}: ok", name);
+            continue;
+        }
+        println!("* {}", name);
+        for issue in &issues {
+            println!("  - {}", issue);
+        }
+        if issues.iter().any(|v| v.is_error(args.strict)) {
+            failed.push(name.clone());
+        }
+    }
+
+    if !failed.is_empty() {
+        return Err(Error::CheckFailed(failed.join(", ")));
+    }
+    info!("Every profile matches the schema");
+    Ok(())
+}
+
+/// Checks the content of the profile `name`, as it is about to be applied, against the
+/// schema of the project. Nothing is checked when the project has no schema.
+pub(crate) fn ensure_valid(pwd: &Path, name: &str, env: &str) -> Result<(), Error> {
+    let schema = match common::Schema::load(pwd)? {
+        Some(v) => v,
+        None => return Ok(()),
+    };
+    let issues = schema.check(&common::EnvFile::parse(env)?);
+    for issue in &issues {
+        warn!("{}", issue);
+    }
+    if issues.iter().any(|v| v.is_error(false)) {
+        return Err(Error::CheckFailed(name.to_string()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_ensure_valid() {
+        let tmp_dir = TempDir::new("test_ensure_valid").unwrap();
+        let tmp_dir_path = tmp_dir.path().to_owned();
+        ensure_valid(&tmp_dir_path, "dev", "A=1").unwrap();
+
+        fs::write(tmp_dir_path.join(common::EXAMPLE_FILENAME), "A=\nB=\n").unwrap();
+        assert!(matches!(
+            ensure_valid(&tmp_dir_path, "dev", "A=1"),
+            Err(Error::CheckFailed(_))
+        ));
+
+        fs::write(tmp_dir_path.join(common::SCHEMA_FILENAME), "A=int\n").unwrap();
+        ensure_valid(&tmp_dir_path, "dev", "A=1\nC=3").unwrap();
+        assert!(ensure_valid(&tmp_dir_path, "dev", "A=one").is_err());
+    }
+}
@@ -0,0 +1,55 @@
+use log::info;
+use std::result::Result;
+use structopt::StructOpt;
+
+use crate::commands::add;
+use crate::common;
+use crate::error::Error;
+
+#[derive(Debug, PartialEq, StructOpt)]
+pub struct Args {
+    /// Profile to copy
+    src: String,
+
+    /// Name of the new profile
+    dst: String,
+}
+
+pub fn execute(args: &Args) -> Result<(), Error> {
+    let common::Workspace {
+        project_dir,
+        project_id,
+        ..
+    } = common::open_workspace()?;
+    let _lock = common::lock_project(&project_dir, &project_id)?;
+    let cipher = common::load_cipher(&project_dir, &project_id).map_err(Error::Encryption)?;
+    let (config_path, mut project) =
+        common::read_config(&project_dir, &project_id, cipher.as_ref())
+            .map_err(Error::CorruptConfig)?;
+
+    let profile = common::select_profile(&project, &args.src).map_err(|_| Error::NotExists)?;
+    let (bases, protected) = (profile.bases.clone(), profile.protected);
+    let (_, env) = common::read_env(
+        &project_dir,
+        &project_id,
+        &project,
+        profile,
+        cipher.as_ref(),
+    )?;
+
+    add::insert_profile(
+        &project_dir,
+        &project_id,
+        &mut project,
+        &args.dst,
+        &env.unwrap_or_default(),
+        cipher.as_ref(),
+    )?;
+    if let Some(entry) = project.entries.iter_mut().find(|v| v.name == args.dst) {
+        entry.bases = bases;
+        entry.protected = protected;
+    }
+    common::save_config(&project, &config_path, cipher.as_ref())?;
+    info!("Successfully cloned {} to {}", args.src, args.dst);
+    Ok(())
+}
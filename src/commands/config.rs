@@ -0,0 +1,115 @@
+use log::info;
+use std::result::Result;
+use structopt::StructOpt;
+
+use crate::common;
+use crate::error::Error;
+
+#[derive(Debug, PartialEq, StructOpt)]
+pub struct Args {
+    /// Name of the setting. Available settings: auto-confirm, apply-mode, interpolation, history-limit
+    key: String,
+
+    /// New value of the setting. The current value is printed when it is omitted.
+    value: Option<String>,
+}
+
+pub fn execute(args: &Args) -> Result<(), Error> {
+    let common::Workspace {
+        project_dir,
+        project_id,
+        ..
+    } = common::open_workspace()?;
+    // Reading a setting does not need to wait for other commands
+    let _lock = match args.value {
+        Some(_) => Some(common::lock_project(&project_dir, &project_id)?),
+        None => None,
+    };
+    let cipher = common::load_cipher(&project_dir, &project_id).map_err(Error::Encryption)?;
+    let (config_path, mut project) =
+        common::read_config(&project_dir, &project_id, cipher.as_ref())
+            .map_err(Error::CorruptConfig)?;
+
+    match &args.value {
+        Some(v) => {
+            set_setting(&mut project, &args.key, v)?;
+            common::prune_history(&project_dir, &project_id, &mut project)?;
+            common::save_config(&project, &config_path, cipher.as_ref())?;
+            info!("Successfully updated {}", args.key);
+        }
+        None => println!("{}", get_setting(&project, &args.key)?),
+    }
+    Ok(())
+}
+
+fn get_setting(project: &common::Project, key: &str) -> Result<String, Error> {
+    match key {
+        "auto-confirm" => Ok(project.auto_confirm.to_string()),
+        "apply-mode" => Ok(project.apply_mode.to_string()),
+        "interpolation" => Ok(project.interpolation.to_string()),
+        "history-limit" => Ok(project.history_limit().to_string()),
+        _ => Err(unknown_setting(key)),
+    }
+}
+
+fn set_setting(project: &mut common::Project, key: &str, value: &str) -> Result<(), Error> {
+    match key {
+        "auto-confirm" => project.auto_confirm = parse_bool(value)?,
+        "apply-mode" => project.apply_mode = value.parse().map_err(Error::InvalidArgument)?,
+        "interpolation" => project.interpolation = value.parse().map_err(Error::InvalidArgument)?,
+        "history-limit" => project.history_limit = Some(parse_limit(value)?),
+        _ => return Err(unknown_setting(key)),
+    }
+    Ok(())
+}
+
+fn parse_bool(value: &str) -> Result<bool, Error> {
+    value
+        .parse()
+        .map_err(|_| Error::InvalidArgument(format!("{} is not true or false", value)))
+}
+
+fn parse_limit(value: &str) -> Result<usize, Error> {
+    value.parse().map_err(|_| {
+        Error::InvalidArgument(format!(
+            "{} is not a number of revisions. Use 0 to keep no history",
+            value
+        ))
+    })
+}
+
+fn unknown_setting(key: &str) -> Error {
+    Error::InvalidArgument(format!("{} is not a valid setting", key))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_set_and_get_setting() {
+        let mut project = common::Project::default();
+
+        set_setting(&mut project, "auto-confirm", "true").unwrap();
+        assert!(project.auto_confirm);
+        assert_eq!(get_setting(&project, "auto-confirm").unwrap(), "true");
+
+        assert!(set_setting(&mut project, "auto-confirm", "maybe").is_err());
+
+        set_setting(&mut project, "apply-mode", "copy").unwrap();
+        assert_eq!(project.apply_mode, common::ApplyMode::Copy);
+        assert_eq!(get_setting(&project, "apply-mode").unwrap(), "copy");
+        assert!(set_setting(&mut project, "apply-mode", "move").is_err());
+
+        set_setting(&mut project, "interpolation", "env").unwrap();
+        assert_eq!(project.interpolation, common::Interpolation::Env);
+        assert!(set_setting(&mut project, "interpolation", "shell").is_err());
+
+        assert_eq!(get_setting(&project, "history-limit").unwrap(), "10");
+        set_setting(&mut project, "history-limit", "3").unwrap();
+        assert_eq!(project.history_limit(), 3);
+        assert!(set_setting(&mut project, "history-limit", "-1").is_err());
+        assert!(set_setting(&mut project, "unknown", "true").is_err());
+        assert!(get_setting(&project, "unknown").is_err());
+    }
+}
@@ -1,54 +1,20 @@
-use log::error;
-use std::env;
 use std::result::Result;
-use thiserror::Error;
 
 use crate::common;
-
-#[derive(Error, Debug)]
-enum CurrentCommandError {
-    #[error("reading .rpilot file failed. Make sure this project is initialised properly.")]
-    NotInitialized,
-
-    #[error("the specified profile name does not exists for this project. Please make sure that you are passing the correvt name.")]
-    NotExists,
-
-    #[error(
-        "no profile has been applied yet. Please use apply command to use the certain profile"
-    )]
-    NoProfileIsApplied,
-
-    #[error("Failed at reading the config")]
-    ConfigReadError,
-
-    #[error("external library failed")]
-    ExternalFail(#[from] std::io::Error),
-}
-
-pub fn execute() {
-    match _execute() {
-        Ok(_) => (),
-        Err(e) => {
-            error!("{}", e);
-        }
-    }
-}
-
-fn _execute() -> Result<(), CurrentCommandError> {
-    let pwd = env::current_dir()?;
-    let project_dir = common::get_data_dir()?;
-    let project_id = common::get_project_id(&pwd);
-
-    if project_id.is_none() {
-        return Err(CurrentCommandError::NotInitialized);
-    }
-
-    let project_id = project_id.unwrap();
-    let (_, project) = common::read_config(&project_dir, &project_id)
-        .map_err(|_| CurrentCommandError::ConfigReadError)?;
+use crate::error::Error;
+
+pub fn execute() -> Result<(), Error> {
+    let common::Workspace {
+        project_dir,
+        project_id,
+        ..
+    } = common::open_workspace()?;
+    let cipher = common::load_cipher(&project_dir, &project_id).map_err(Error::Encryption)?;
+    let (_, project) = common::read_config(&project_dir, &project_id, cipher.as_ref())
+        .map_err(Error::CorruptConfig)?;
 
     if project.current_profile.as_ref().is_none() {
-        return Err(CurrentCommandError::NoProfileIsApplied);
+        return Err(Error::NoProfileIsApplied);
     }
 
     let empty_name = "".to_string();
@@ -58,10 +24,16 @@ fn _execute() -> Result<(), CurrentCommandError> {
         .as_ref()
         .unwrap_or(&empty_name);
 
-    let profile = common::select_profile(&project, current_profile)
-        .map_err(|_| CurrentCommandError::NotExists)?;
+    let profile =
+        common::select_profile(&project, current_profile).map_err(|_| Error::NotExists)?;
 
-    let (_, env) = common::read_env(&project_dir, &project_id, &profile.id);
+    let (_, env) = common::read_env(
+        &project_dir,
+        &project_id,
+        &project,
+        profile,
+        cipher.as_ref(),
+    )?;
     println!("The current profile is {}", current_profile);
     println!("---------------------------");
     println!("{}", env.unwrap_or_else(|| "".to_string()));
@@ -0,0 +1,262 @@
+use serde::Serialize;
+use std::fs;
+use std::path::PathBuf;
+use std::result::Result;
+use structopt::StructOpt;
+
+use crate::common;
+use crate::error::Error;
+
+const MASK: &str = "****";
+
+#[derive(Debug, PartialEq, StructOpt)]
+pub struct Args {
+    /// Profile to compare from
+    #[structopt(required_unless = "name")]
+    from: Option<String>,
+
+    /// Profile to compare to
+    #[structopt(required_unless_one = &["against", "name"])]
+    to: Option<String>,
+
+    /// Compare the profile with an env file, such as .env, instead of another profile
+    #[structopt(long, parse(from_os_str), conflicts_with = "to")]
+    against: Option<PathBuf>,
+
+    /// Compare a revision of this profile with its current content instead of two profiles
+    #[structopt(short, long, requires = "rev", conflicts_with_all = &["from", "to", "against"])]
+    name: Option<String>,
+
+    /// Number of the revision to compare. See history command.
+    #[structopt(long, requires = "name")]
+    rev: Option<u32>,
+
+    /// Print the values instead of masking them
+    #[structopt(long)]
+    show_values: bool,
+
+    /// Print the differences as JSON instead of a unified diff
+    #[structopt(long)]
+    json: bool,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Status {
+    Added,
+    Removed,
+    Changed,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub(crate) struct Change {
+    pub key: String,
+    pub status: Status,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Report<'a> {
+    from: &'a str,
+    to: &'a str,
+    changes: &'a [Change],
+}
+
+pub fn execute(args: &Args) -> Result<(), Error> {
+    let common::Workspace {
+        project_dir,
+        project_id,
+        ..
+    } = common::open_workspace()?;
+    let cipher = common::load_cipher(&project_dir, &project_id).map_err(Error::Encryption)?;
+    let (_, project) = common::read_config(&project_dir, &project_id, cipher.as_ref())
+        .map_err(Error::CorruptConfig)?;
+
+    let read_profile = |name: &str| -> Result<common::EnvFile, Error> {
+        let profile = common::select_profile(&project, name).map_err(|_| Error::NotExists)?;
+        common::resolve(
+            &project_dir,
+            &project_id,
+            &project,
+            &profile.id,
+            cipher.as_ref(),
+        )
+    };
+
+    let (from_label, from, to_label, to) = match (&args.name, args.rev, &args.from) {
+        (Some(name), Some(rev), _) => {
+            let profile = common::select_profile(&project, name).map_err(|_| Error::NotExists)?;
+            let revision = common::read_revision(
+                &project_dir,
+                &project_id,
+                &project,
+                profile,
+                rev,
+                cipher.as_ref(),
+            )?;
+            let (_, current) = common::read_env(
+                &project_dir,
+                &project_id,
+                &project,
+                profile,
+                cipher.as_ref(),
+            )?;
+            (
+                format!("{} revision {}", name, rev),
+                common::EnvFile::parse(&revision)?,
+                name.clone(),
+                common::EnvFile::parse(&current.unwrap_or_default())?,
+            )
+        }
+        (_, _, Some(from)) => {
+            let (to_label, to) = match (&args.to, &args.against) {
+                (_, Some(path)) => (
+                    path.display().to_string(),
+                    common::EnvFile::parse(&fs::read_to_string(path)?)?,
+                ),
+                (Some(name), None) => (name.clone(), read_profile(name)?),
+                (None, None) => unreachable!("structopt requires either of them"),
+            };
+            (from.clone(), read_profile(from)?, to_label, to)
+        }
+        _ => unreachable!("structopt requires either a profile or a revision"),
+    };
+
+    let mut changes = compare(&from, &to);
+    if args.json {
+        if !args.show_values {
+            mask(&mut changes);
+        }
+        let report = Report {
+            from: &from_label,
+            to: &to_label,
+            changes: &changes,
+        };
+        let json = serde_json::to_string_pretty(&report).map_err(std::io::Error::other)?;
+        println!("{}", json);
+    } else if !changes.is_empty() {
+        print!(
+            "{}",
+            unified(&from_label, &to_label, &changes, args.show_values)
+        );
+    }
+    Ok(())
+}
+
+/// Lists the keys which differ between two env files. The keys of `from` come first in their
+/// order in the file, followed by the keys which only exist in `to`.
+pub(crate) fn compare(from: &common::EnvFile, to: &common::EnvFile) -> Vec<Change> {
+    let mut changes = Vec::new();
+    for key in keys(from) {
+        let old = from.get(&key).map(String::from);
+        match to.get(&key) {
+            None => changes.push(Change {
+                key,
+                status: Status::Removed,
+                old,
+                new: None,
+            }),
+            Some(v) if old.as_deref() != Some(v) => changes.push(Change {
+                key,
+                status: Status::Changed,
+                old,
+                new: Some(v.to_string()),
+            }),
+            Some(_) => (),
+        }
+    }
+    for key in keys(to) {
+        if from.get(&key).is_none() {
+            let new = to.get(&key).map(String::from);
+            changes.push(Change {
+                key,
+                status: Status::Added,
+                old: None,
+                new,
+            });
+        }
+    }
+    changes
+}
+
+fn keys(env: &common::EnvFile) -> Vec<String> {
+    let mut keys: Vec<String> = Vec::new();
+    for variable in env.variables() {
+        if !keys.contains(&variable.key) {
+            keys.push(variable.key.clone());
+        }
+    }
+    keys
+}
+
+fn mask(changes: &mut [Change]) {
+    for change in changes {
+        change.old = change.old.as_ref().map(|_| MASK.to_string());
+        change.new = change.new.as_ref().map(|_| MASK.to_string());
+    }
+}
+
+fn unified(from: &str, to: &str, changes: &[Change], show_values: bool) -> String {
+    let line = |key: &str, value: &str| {
+        if show_values {
+            common::Variable::new(key, value).to_string()
+        } else {
+            format!("{}={}", key, MASK)
+        }
+    };
+    let mut output = format!("--- {}\n+++ {}\n", from, to);
+    for change in changes {
+        if let Some(old) = &change.old {
+            output.push_str(&format!("-{}\n", line(&change.key, old)));
+        }
+        if let Some(new) = &change.new {
+            output.push_str(&format!("+{}\n", line(&change.key, new)));
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_compare() {
+        let from = common::EnvFile::parse("A=1\nB=2\nC=3\nB=4").unwrap();
+        let to = common::EnvFile::parse("D=5\nC=3\nA=one").unwrap();
+
+        let changes = compare(&from, &to);
+        let summary = changes
+            .iter()
+            .map(|v| (v.key.as_str(), &v.status))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                ("A", &Status::Changed),
+                ("B", &Status::Removed),
+                ("D", &Status::Added)
+            ]
+        );
+        assert_eq!(changes[1].old, Some("4".to_string()));
+    }
+
+    #[test]
+    fn test_unified_masks_values() {
+        let from = common::EnvFile::parse("A=1\nB=2").unwrap();
+        let to = common::EnvFile::parse("A=two words").unwrap();
+        let mut changes = compare(&from, &to);
+        assert_eq!(
+            unified("dev", "prod", &changes, true),
+            "--- dev\n+++ prod\n-A=1\n+A=\"two words\"\n-B=2\n"
+        );
+        assert_eq!(
+            unified("dev", "prod", &changes, false),
+            "--- dev\n+++ prod\n-A=****\n+A=****\n-B=****\n"
+        );
+
+        mask(&mut changes);
+        assert_eq!(changes[0].old.as_deref(), Some(MASK));
+    }
+}
@@ -1,68 +1,112 @@
-use log::error;
-use std::env;
-use std::fs;
-use std::io::Result as SimpleResult;
-use std::path::Path;
+use log::{info, warn};
+use std::path::{Path, PathBuf};
 use std::result::Result;
 use structopt::StructOpt;
-use thiserror::Error;
 
+use crate::commands::{add, apply};
 use crate::common;
+use crate::error::Error;
 
 #[derive(Debug, PartialEq, StructOpt)]
 pub struct Args {
     #[structopt(short, long)]
     name: String,
-}
-
-#[derive(Error, Debug)]
-enum EditCommandError {
-    #[error("reading .rpilot file failed. Make sure this project is initialised properly.")]
-    NotInitialized,
 
-    #[error("the specified profile name does not exists for this project. Please make sure that you are passing the correvt name.")]
-    NotExists,
+    /// Replace the bases of the profile instead of opening the editor. Can be repeated.
+    #[structopt(long = "base")]
+    bases: Vec<String>,
 
-    #[error("Failed at reading the config")]
-    ConfigReadError,
+    /// Stop inheriting from other profiles instead of opening the editor
+    #[structopt(long, conflicts_with = "bases")]
+    no_bases: bool,
 
-    #[error("external library failed")]
-    ExternalFail(#[from] std::io::Error),
-}
-
-pub fn execute(args: &Args) {
-    match _execute(args) {
-        Ok(_) => (),
-        Err(e) => {
-            error!("{}", e);
-        }
-    }
+    /// Describe the change in the history of the profile
+    #[structopt(short, long)]
+    message: Option<String>,
 }
 
-fn _execute(args: &Args) -> Result<(), EditCommandError> {
-    let pwd = env::current_dir()?;
-    let project_dir = common::get_data_dir()?;
-    let project_id = common::get_project_id(&pwd);
+pub fn execute(args: &Args) -> Result<(), Error> {
+    let common::Workspace {
+        pwd,
+        project_dir,
+        project_id,
+    } = common::open_workspace()?;
+    let cipher = common::load_cipher(&project_dir, &project_id).map_err(Error::Encryption)?;
 
-    if project_id.is_none() {
-        return Err(EditCommandError::NotInitialized);
+    if !args.bases.is_empty() || args.no_bases {
+        let _lock = common::lock_project(&project_dir, &project_id)?;
+        let (config_path, mut project) =
+            common::read_config(&project_dir, &project_id, cipher.as_ref())
+                .map_err(Error::CorruptConfig)?;
+        add::set_bases(&mut project, &args.name, &args.bases)?;
+        apply::refresh_applied_copy(
+            &pwd,
+            &project_dir,
+            &project_id,
+            &mut project,
+            cipher.as_ref(),
+        )?;
+        common::save_config(&project, &config_path, cipher.as_ref())?;
+        info!("Successfully updated the bases of {}", args.name);
+        return Ok(());
     }
 
-    let project_id = project_id.unwrap();
-    let (_, project) = common::read_config(&project_dir, &project_id)
-        .map_err(|_| EditCommandError::ConfigReadError)?;
-
-    let profile =
-        common::select_profile(&project, &args.name).map_err(|_| EditCommandError::NotExists)?;
+    // The editor runs without the lock, so that other commands can run meanwhile
+    let (_, _, previous) = read_profile(&project_dir, &project_id, &args.name, cipher.as_ref())?;
+    let edited = edit::edit(&previous)?;
+    if edited == previous {
+        info!("The profile {} was not changed", args.name);
+        return Ok(());
+    }
+    common::EnvFile::parse(&edited)?;
 
-    let (env_path, env) = common::read_env(&project_dir, &project_id, &profile.id);
-    edit_and_save(&env_path, env.unwrap_or_else(|| "".to_string()))?;
+    let _lock = common::lock_project(&project_dir, &project_id)?;
+    let (config_path, mut project, current) =
+        read_profile(&project_dir, &project_id, &args.name, cipher.as_ref())?;
+    if current != previous {
+        warn!(
+            "The profile {} was changed while it was edited. The change is kept in its history",
+            args.name
+        );
+    }
+    common::record_revision(
+        &project_dir,
+        &project_id,
+        &mut project,
+        &args.name,
+        args.message.as_deref().unwrap_or_default(),
+    )?;
+    common::write_profile(
+        &project_dir,
+        &project_id,
+        &mut project,
+        &args.name,
+        &edited,
+        cipher.as_ref(),
+    )?;
+    apply::refresh_applied_copy(
+        &pwd,
+        &project_dir,
+        &project_id,
+        &mut project,
+        cipher.as_ref(),
+    )?;
+    common::save_config(&project, &config_path, cipher.as_ref())?;
+    info!("Successfully updated the profile {}", args.name);
 
     Ok(())
 }
 
-fn edit_and_save(env_path: &Path, env: String) -> SimpleResult<()> {
-    let edited = edit::edit(env)?;
-    fs::write(env_path, edited)?;
-    Ok(())
+/// Reads the config and the content of the profile `name`
+fn read_profile(
+    project_dir: &Path,
+    project_id: &str,
+    name: &str,
+    cipher: Option<&common::Cipher>,
+) -> Result<(PathBuf, common::Project, String), Error> {
+    let (config_path, project) =
+        common::read_config(project_dir, project_id, cipher).map_err(Error::CorruptConfig)?;
+    let profile = common::select_profile(&project, name).map_err(|_| Error::NotExists)?;
+    let (_, env) = common::read_env(project_dir, project_id, &project, profile, cipher)?;
+    Ok((config_path, project, env.unwrap_or_default()))
 }
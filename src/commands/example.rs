@@ -0,0 +1,118 @@
+use log::info;
+use std::path::PathBuf;
+use std::result::Result;
+use structopt::StructOpt;
+
+use crate::common;
+use crate::error::Error;
+
+#[derive(Debug, PartialEq, StructOpt)]
+pub struct Args {
+    #[structopt(short, long, required_unless = "all", conflicts_with = "all")]
+    name: Option<String>,
+
+    /// Use the keys of every profile of the project
+    #[structopt(long)]
+    all: bool,
+
+    /// Value to put instead of the real values. `{key}` is replaced by the name of the variable.
+    /// The values are left empty by default.
+    #[structopt(long)]
+    placeholder: Option<String>,
+
+    /// Write the example to this file instead of printing it
+    #[structopt(short, long, parse(from_os_str))]
+    output: Option<PathBuf>,
+}
+
+pub fn execute(args: &Args) -> Result<(), Error> {
+    let common::Workspace {
+        project_dir,
+        project_id,
+        ..
+    } = common::open_workspace()?;
+    let cipher = common::load_cipher(&project_dir, &project_id).map_err(Error::Encryption)?;
+    let (_, project) = common::read_config(&project_dir, &project_id, cipher.as_ref())
+        .map_err(Error::CorruptConfig)?;
+
+    let profiles = match &args.name {
+        Some(name) => vec![common::select_profile(&project, name).map_err(|_| Error::NotExists)?],
+        None => project.entries.iter().collect(),
+    };
+    let mut envs = Vec::new();
+    for profile in profiles {
+        envs.push(common::resolve(
+            &project_dir,
+            &project_id,
+            &project,
+            &profile.id,
+            cipher.as_ref(),
+        )?);
+    }
+
+    let example = strip_values(&union(&envs), args.placeholder.as_deref());
+    match &args.output {
+        Some(path) => {
+            common::write_atomic(path, example.to_string().as_bytes())?;
+            info!("Wrote the example to {}", path.display());
+        }
+        None => print!("{}", example),
+    }
+    Ok(())
+}
+
+/// Returns the first env file with the keys of the others which it does not have appended
+fn union(envs: &[common::EnvFile]) -> common::EnvFile {
+    let mut iter = envs.iter();
+    let mut merged = iter.next().cloned().unwrap_or_default();
+    for env in iter {
+        for variable in env.variables() {
+            if merged.get(&variable.key).is_none() {
+                merged.set(&variable.key, &variable.value);
+            }
+        }
+    }
+    merged
+}
+
+/// Replaces every value with the placeholder, keeping the comments and the order of the keys
+fn strip_values(env: &common::EnvFile, placeholder: Option<&str>) -> common::EnvFile {
+    let mut stripped = env.clone();
+    for variable in stripped.variables_mut() {
+        let value = placeholder
+            .map(|v| v.replace("{key}", &variable.key))
+            .unwrap_or_default();
+        variable.set_value(&value);
+    }
+    stripped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_strip_values() {
+        let env = common::EnvFile::parse(
+            "# database\nexport DB_URL=postgres://x # main\nTOKEN='s3cr3t'\n",
+        )
+        .unwrap();
+        assert_eq!(
+            strip_values(&env, None).to_string(),
+            "# database\nexport DB_URL= # main\nTOKEN=\n"
+        );
+        assert_eq!(
+            strip_values(&env, Some("<{key}>")).to_string(),
+            "# database\nexport DB_URL=\"<DB_URL>\" # main\nTOKEN=\"<TOKEN>\"\n"
+        );
+    }
+
+    #[test]
+    fn test_union() {
+        let envs = vec![
+            common::EnvFile::parse("A=1\nB=2\n").unwrap(),
+            common::EnvFile::parse("B=3\nC=4\n").unwrap(),
+        ];
+        assert_eq!(union(&envs).to_string(), "A=1\nB=2\nC=4\n");
+    }
+}
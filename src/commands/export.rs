@@ -0,0 +1,168 @@
+use log::warn;
+use std::fmt;
+use std::result::Result;
+use std::str::FromStr;
+use structopt::StructOpt;
+
+use crate::common;
+use crate::error::Error;
+
+#[derive(Debug, PartialEq, StructOpt)]
+pub struct Args {
+    #[structopt(short, long)]
+    name: String,
+
+    /// Shell to print the commands for: bash, zsh, sh or fish
+    #[structopt(short, long, default_value = "sh")]
+    shell: Shell,
+
+    /// Print the commands which remove the variables of the profile instead
+    #[structopt(long)]
+    unset: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Sh,
+    Fish,
+}
+
+impl fmt::Display for Shell {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Shell::Bash => "bash",
+            Shell::Zsh => "zsh",
+            Shell::Sh => "sh",
+            Shell::Fish => "fish",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Shell {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bash" => Ok(Shell::Bash),
+            "zsh" => Ok(Shell::Zsh),
+            "sh" => Ok(Shell::Sh),
+            "fish" => Ok(Shell::Fish),
+            _ => Err(format!(
+                "{} is not a supported shell. Use bash, zsh, sh or fish",
+                s
+            )),
+        }
+    }
+}
+
+pub fn execute(args: &Args) -> Result<(), Error> {
+    let common::Workspace {
+        project_dir,
+        project_id,
+        ..
+    } = common::open_workspace()?;
+    let cipher = common::load_cipher(&project_dir, &project_id).map_err(Error::Encryption)?;
+    let (_, project) = common::read_config(&project_dir, &project_id, cipher.as_ref())
+        .map_err(Error::CorruptConfig)?;
+
+    let profile = common::select_profile(&project, &args.name).map_err(|_| Error::NotExists)?;
+    let env = common::materialize(
+        &project_dir,
+        &project_id,
+        &project,
+        &profile.id,
+        cipher.as_ref(),
+    )?;
+    let env = common::EnvFile::parse(&env)?;
+
+    let variables = exportable_variables(&env);
+    if args.unset {
+        let keys = variables.iter().map(|(k, _)| *k).collect::<Vec<&str>>();
+        print!("{}", unset_commands(args.shell, &keys));
+    } else {
+        print!("{}", export_commands(args.shell, &variables));
+    }
+    Ok(())
+}
+
+/// Returns the variables of `env` which can be set in a shell, with the last assignment of
+/// each key. Keys which are not valid shell names, such as `a.b`, are skipped with a warning.
+pub(crate) fn exportable_variables(env: &common::EnvFile) -> Vec<(&str, &str)> {
+    let mut variables: Vec<(&str, &str)> = Vec::new();
+    for variable in env.variables() {
+        if variable.key.contains('.') {
+            warn!(
+                "Skipped {} which is not a valid name in a shell",
+                variable.key
+            );
+            continue;
+        }
+        match variables.iter_mut().find(|(k, _)| *k == variable.key) {
+            Some(v) => v.1 = &variable.value,
+            None => variables.push((&variable.key, &variable.value)),
+        }
+    }
+    variables
+}
+
+pub(crate) fn export_commands(shell: Shell, variables: &[(&str, &str)]) -> String {
+    variables
+        .iter()
+        .map(|(key, value)| match shell {
+            Shell::Fish => format!("set -gx {} {};\n", key, quote_fish(value)),
+            _ => format!("export {}={};\n", key, quote_posix(value)),
+        })
+        .collect()
+}
+
+pub(crate) fn unset_commands(shell: Shell, keys: &[&str]) -> String {
+    keys.iter()
+        .map(|key| match shell {
+            Shell::Fish => format!("set -e {};\n", key),
+            _ => format!("unset {};\n", key),
+        })
+        .collect()
+}
+
+/// Single quotes keep everything literal in a POSIX shell. A single quote itself has to
+/// close the quotes, be escaped and open them again.
+pub(crate) fn quote_posix(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// Inside single quotes, fish only interprets `\\` and `\'`
+fn quote_fish(value: &str) -> String {
+    format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_export_commands() {
+        let variables = vec![("A", "it's $HOME"), ("B", "back\\slash\nnewline")];
+        assert_eq!(
+            export_commands(Shell::Bash, &variables),
+            "export A='it'\\''s $HOME';\nexport B='back\\slash\nnewline';\n"
+        );
+        assert_eq!(
+            export_commands(Shell::Fish, &variables),
+            "set -gx A 'it\\'s $HOME';\nset -gx B 'back\\\\slash\nnewline';\n"
+        );
+        assert_eq!(
+            unset_commands(Shell::Zsh, &["A", "B"]),
+            "unset A;\nunset B;\n"
+        );
+        assert_eq!(unset_commands(Shell::Fish, &["A"]), "set -e A;\n");
+    }
+
+    #[test]
+    fn test_exportable_variables() {
+        let env = common::EnvFile::parse("A=1\nspring.port=80\nA=2\nB=3").unwrap();
+        assert_eq!(exportable_variables(&env), vec![("A", "2"), ("B", "3")]);
+    }
+}
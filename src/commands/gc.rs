@@ -0,0 +1,263 @@
+use log::{debug, info, warn};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::env;
+use std::fs;
+use std::io::{Result as SimpleResult, Write};
+use std::path::{Path, PathBuf};
+use std::result::Result;
+use structopt::StructOpt;
+use uuid::Uuid;
+
+use crate::common;
+use crate::error::Error;
+
+#[derive(Debug, PartialEq, StructOpt)]
+pub struct Args {
+    /// Delete without asking for a confirmation
+    #[structopt(short, long)]
+    yes: bool,
+
+    /// Only show what would be deleted
+    #[structopt(long)]
+    dry_run: bool,
+
+    /// Overwrite the content of the files with random bytes before deleting them
+    #[structopt(long)]
+    shred: bool,
+}
+
+pub fn execute(args: &Args) -> Result<(), Error> {
+    let project_dir = common::get_data_dir()?;
+    let current_id = env::current_dir()
+        .ok()
+        .and_then(|pwd| common::find_project_root(&pwd))
+        .and_then(|root| common::get_project_id(&root));
+
+    let mut abandoned = Vec::new();
+    let mut orphans = Vec::new();
+    // Held until the files are deleted, so that no profile is added in between
+    let mut locks = Vec::new();
+    for id in list_projects(&project_dir)? {
+        locks.push(common::lock_project(&project_dir, &id)?);
+        if let Some(checkout) = find_abandoned(&project_dir, &id) {
+            abandoned.push((project_dir.join(&id), checkout));
+            continue;
+        }
+
+        // Reading the profiles of an encrypted project needs its passphrase, so only the
+        // current one is checked
+        let cipher = if !common::is_project_encrypted(&project_dir, &id) {
+            None
+        } else if current_id.as_deref() == Some(&id) {
+            common::load_cipher(&project_dir, &id).map_err(Error::Encryption)?
+        } else {
+            info!(
+                "Skipped the encrypted project {}. Run gc in its directory to check its profiles.",
+                id
+            );
+            continue;
+        };
+        // Without its config every profile of the project would look orphaned
+        let project = match common::read_config(&project_dir, &id, cipher.as_ref()) {
+            Ok((_, v)) => v,
+            Err(e) => {
+                warn!("Skipped the project {}: {}", id, e);
+                continue;
+            }
+        };
+        orphans.extend(find_orphans(&project_dir.join(&id), &project)?);
+    }
+
+    if abandoned.is_empty() && orphans.is_empty() {
+        println!("Nothing to clean up");
+        return Ok(());
+    }
+    if !orphans.is_empty() {
+        println!("Profile files which are not used by any profile");
+        for path in &orphans {
+            println!("* {}", path.display());
+        }
+    }
+    if !abandoned.is_empty() {
+        println!("Projects which are not checked out anymore");
+        for (path, checkout) in &abandoned {
+            println!(
+                "* {} (checked out at {})",
+                path.display(),
+                checkout.display()
+            );
+        }
+    }
+    if args.dry_run {
+        return Ok(());
+    }
+    confirm_gc(args)?;
+
+    for path in orphans.iter().chain(abandoned.iter().map(|(path, _)| path)) {
+        remove_path(path, args.shred)?;
+        debug!("Deleted {}", path.display());
+    }
+    info!("Successfully cleaned up the data dir");
+    Ok(())
+}
+
+fn confirm_gc(args: &Args) -> Result<(), Error> {
+    if args.yes {
+        return Ok(());
+    }
+    if !common::is_interactive() {
+        return Err(Error::NonInteractive(
+            "deleting the files needs a confirmation. Please pass --yes to delete them from a script."
+                .to_string(),
+        ));
+    }
+    match common::prompt("Delete them? [Y/N]")?.as_deref() {
+        Some("Y") | Some("y") => Ok(()),
+        _ => Err(Error::Aborted),
+    }
+}
+
+fn list_projects(project_dir: &Path) -> SimpleResult<Vec<String>> {
+    if !project_dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut ids = fs::read_dir(project_dir)?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().map(|v| v.is_dir()).unwrap_or(false))
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect::<Vec<String>>();
+    ids.sort();
+    Ok(ids)
+}
+
+/// Returns the recorded checkout of a project when it does not contain the `.rpilot` of
+/// the project anymore. Projects without a recorded checkout are never abandoned.
+fn find_abandoned(project_dir: &Path, id: &str) -> Option<PathBuf> {
+    let checkout = common::read_checkout(project_dir, id)?;
+    match common::get_project_id(&checkout) {
+        Some(v) if v == id => None,
+        _ => Some(checkout),
+    }
+}
+
+/// Returns the env files in the data dir of a project which no entry refers to
+fn find_orphans(data_dir: &Path, project: &common::Project) -> SimpleResult<Vec<PathBuf>> {
+    let mut orphans = fs::read_dir(data_dir)?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().map(|v| v.is_file()).unwrap_or(false))
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| Uuid::parse_str(name).is_ok())
+        .filter(|name| !project.entries.iter().any(|entry| &entry.id == name))
+        .map(|name| data_dir.join(name))
+        .collect::<Vec<PathBuf>>();
+    // The history of a removed profile is kept until gc, like its file
+    let history_dir = data_dir.join(common::HISTORY_DIRNAME);
+    if let Ok(entries) = fs::read_dir(&history_dir) {
+        orphans.extend(
+            entries
+                .filter_map(|entry| entry.ok())
+                .filter_map(|entry| entry.file_name().into_string().ok())
+                .filter(|name| !project.entries.iter().any(|entry| &entry.id == name))
+                .map(|name| history_dir.join(name)),
+        );
+    }
+    orphans.sort();
+    Ok(orphans)
+}
+
+fn remove_path(path: &Path, shred: bool) -> SimpleResult<()> {
+    let metadata = fs::symlink_metadata(path)?;
+    if metadata.is_dir() {
+        if shred {
+            for entry in fs::read_dir(path)? {
+                remove_path(&entry?.path(), shred)?;
+            }
+        }
+        return fs::remove_dir_all(path);
+    }
+    if shred && metadata.is_file() {
+        shred_file(path)?;
+    }
+    fs::remove_file(path)
+}
+
+fn shred_file(path: &Path) -> SimpleResult<()> {
+    let mut remaining = fs::metadata(path)?.len() as usize;
+    let mut file = fs::OpenOptions::new().write(true).open(path)?;
+    let mut buffer = [0; 4096];
+    let rng = SystemRandom::new();
+    while remaining > 0 {
+        let count = remaining.min(buffer.len());
+        rng.fill(&mut buffer[..count])
+            .map_err(|_| std::io::Error::other("Failed at generating random bytes"))?;
+        file.write_all(&buffer[..count])?;
+        remaining -= count;
+    }
+    file.sync_all()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_find_orphans() {
+        let tmp_dir = TempDir::new("test_find_orphans").unwrap();
+        let tmp_dir_path = tmp_dir.path().to_owned();
+        let used = Uuid::new_v4().to_string();
+        let orphan = Uuid::new_v4().to_string();
+        for name in &[&used, &orphan, "config", "encryption"] {
+            fs::write(tmp_dir_path.join(name), "A=1").unwrap();
+        }
+        let history_dir = tmp_dir_path.join(common::HISTORY_DIRNAME);
+        fs::create_dir_all(history_dir.join(&used)).unwrap();
+        fs::create_dir_all(history_dir.join(&orphan)).unwrap();
+        let project = common::Project {
+            entries: vec![common::Entry {
+                name: "dev".to_string(),
+                id: used.clone(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let orphans = find_orphans(&tmp_dir_path, &project).unwrap();
+        let mut expected = vec![history_dir.join(&orphan), tmp_dir_path.join(&orphan)];
+        expected.sort();
+        assert_eq!(orphans, expected);
+    }
+
+    #[test]
+    fn test_find_abandoned() {
+        let tmp_dir = TempDir::new("test_find_abandoned").unwrap();
+        let tmp_dir_path = tmp_dir.path().to_owned();
+        let checkout = tmp_dir_path.join("checkout");
+        fs::create_dir_all(tmp_dir_path.join("project")).unwrap();
+        fs::create_dir_all(&checkout).unwrap();
+        assert_eq!(find_abandoned(&tmp_dir_path, "project"), None);
+
+        common::record_checkout(&tmp_dir_path, "project", &checkout).unwrap();
+        fs::write(checkout.join(common::ID_FILENAME), "project").unwrap();
+        assert_eq!(find_abandoned(&tmp_dir_path, "project"), None);
+
+        fs::write(checkout.join(common::ID_FILENAME), "other").unwrap();
+        assert_eq!(find_abandoned(&tmp_dir_path, "project"), Some(checkout));
+    }
+
+    #[test]
+    fn test_shred_and_remove_path() {
+        let tmp_dir = TempDir::new("test_shred").unwrap();
+        let tmp_dir_path = tmp_dir.path().to_owned();
+        let file = tmp_dir_path.join("secret");
+        fs::write(&file, "SECRET=value").unwrap();
+
+        shred_file(&file).unwrap();
+        let shredded = fs::read(&file).unwrap();
+        assert_eq!(shredded.len(), "SECRET=value".len());
+        assert_ne!(shredded, b"SECRET=value");
+
+        remove_path(&tmp_dir_path, true).unwrap();
+        assert!(!tmp_dir_path.exists());
+    }
+}
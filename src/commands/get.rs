@@ -0,0 +1,39 @@
+use std::result::Result;
+use structopt::StructOpt;
+
+use crate::common;
+use crate::error::Error;
+
+#[derive(Debug, PartialEq, StructOpt)]
+pub struct Args {
+    #[structopt(short, long)]
+    name: String,
+
+    /// Name of the variable
+    key: String,
+}
+
+pub fn execute(args: &Args) -> Result<(), Error> {
+    let common::Workspace {
+        project_dir,
+        project_id,
+        ..
+    } = common::open_workspace()?;
+    let cipher = common::load_cipher(&project_dir, &project_id).map_err(Error::Encryption)?;
+    let (_, project) = common::read_config(&project_dir, &project_id, cipher.as_ref())
+        .map_err(Error::CorruptConfig)?;
+
+    let profile = common::select_profile(&project, &args.name).map_err(|_| Error::NotExists)?;
+    let env = common::resolve(
+        &project_dir,
+        &project_id,
+        &project,
+        &profile.id,
+        cipher.as_ref(),
+    )?;
+    let value = env
+        .get(&args.key)
+        .ok_or_else(|| Error::NoVariable(args.key.clone()))?;
+    println!("{}", value);
+    Ok(())
+}
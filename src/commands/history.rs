@@ -0,0 +1,72 @@
+use std::result::Result;
+use structopt::StructOpt;
+
+use crate::common;
+use crate::error::Error;
+
+#[derive(Debug, PartialEq, StructOpt)]
+pub struct Args {
+    #[structopt(short, long)]
+    name: String,
+}
+
+pub fn execute(args: &Args) -> Result<(), Error> {
+    let common::Workspace {
+        project_dir,
+        project_id,
+        ..
+    } = common::open_workspace()?;
+    let cipher = common::load_cipher(&project_dir, &project_id).map_err(Error::Encryption)?;
+    let (_, project) = common::read_config(&project_dir, &project_id, cipher.as_ref())
+        .map_err(Error::CorruptConfig)?;
+
+    let profile = common::select_profile(&project, &args.name).map_err(|_| Error::NotExists)?;
+    print!("{}", format_history(profile));
+    Ok(())
+}
+
+/// Lists the revisions from the newest. Each revision is the content of the profile before
+/// the change made at its time.
+fn format_history(entry: &common::Entry) -> String {
+    if entry.history.is_empty() {
+        return format!("{} has no history yet\n", entry.name);
+    }
+    let mut output = format!(
+        "Here are the revisions of {}, which are its contents before each change\n",
+        entry.name
+    );
+    for revision in entry.history.iter().rev() {
+        let line = format!(
+            "* {} {} {}",
+            revision.number, revision.timestamp, revision.message
+        );
+        output.push_str(line.trim_end());
+        output.push('\n');
+    }
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_format_history() {
+        let mut entry = common::Entry {
+            name: "prod".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(format_history(&entry), "prod has no history yet\n");
+
+        for (number, message) in &[(1, ""), (2, "rotate the token")] {
+            entry.history.push(common::Revision {
+                number: *number,
+                timestamp: "20261018T101500Z".to_string(),
+                message: message.to_string(),
+                ..Default::default()
+            });
+        }
+        assert!(format_history(&entry)
+            .ends_with("* 2 20261018T101500Z rotate the token\n* 1 20261018T101500Z\n"));
+    }
+}
@@ -0,0 +1,253 @@
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::result::Result;
+use structopt::StructOpt;
+
+use crate::commands::allow;
+use crate::commands::export::{self, Shell};
+use crate::common;
+use crate::error::Error;
+
+/// Variable in which the shell keeps what the hook loaded, to unload it later
+const STATE_VARIABLE: &str = "RPILOT_HOOK_STATE";
+
+#[derive(Debug, PartialEq, StructOpt)]
+pub struct Args {
+    /// Shell to print the hook for: bash, zsh or fish
+    shell: Shell,
+
+    /// Print the commands which update the environment for the current directory. Used by the hook itself.
+    #[structopt(long, hidden = true)]
+    eval: bool,
+}
+
+/// What the hook loaded into the shell
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct State {
+    dir: PathBuf,
+    profile: String,
+    /// Value of every loaded variable before it was loaded, `None` when it was not set
+    previous: BTreeMap<String, Option<String>>,
+}
+
+/// The current profile of a project, as it is loaded into the shell
+struct Loaded {
+    profile: String,
+    variables: Vec<(String, String)>,
+}
+
+pub fn execute(args: &Args) -> Result<(), Error> {
+    if args.shell == Shell::Sh {
+        return Err(Error::InvalidArgument(
+            "the hook is available for bash, zsh and fish".to_string(),
+        ));
+    }
+    if args.eval {
+        // The previous project is unloaded even when the new one fails to load
+        let mut commands = String::new();
+        let result = eval(args.shell, &mut commands);
+        print!("{}", commands);
+        return result;
+    }
+
+    let exe = env::current_exe()?;
+    print!("{}", hook_script(args.shell, &exe.to_string_lossy()));
+    Ok(())
+}
+
+fn hook_script(shell: Shell, exe: &str) -> String {
+    let exe = export::quote_posix(exe);
+    match shell {
+        Shell::Fish => format!(
+            "function __rpilot_hook --on-variable PWD\n    \
+                 {} hook fish --eval | source\n\
+             end\n\
+             __rpilot_hook\n",
+            exe
+        ),
+        Shell::Zsh => format!(
+            "_rpilot_hook() {{\n  \
+                 if [[ \"$PWD\" != \"${{_RPILOT_PWD-}}\" ]]; then\n    \
+                     _RPILOT_PWD=\"$PWD\"\n    \
+                     eval \"$({} hook zsh --eval)\"\n  \
+                 fi\n\
+             }}\n\
+             typeset -ag precmd_functions\n\
+             if (( ! ${{precmd_functions[(I)_rpilot_hook]}} )); then\n  \
+                 precmd_functions=(_rpilot_hook $precmd_functions)\n\
+             fi\n",
+            exe
+        ),
+        _ => format!(
+            "_rpilot_hook() {{\n  \
+                 local previous_exit_status=$?\n  \
+                 if [ \"$PWD\" != \"${{_RPILOT_PWD-}}\" ]; then\n    \
+                     _RPILOT_PWD=\"$PWD\"\n    \
+                     eval \"$({} hook bash --eval)\"\n  \
+                 fi\n  \
+                 return $previous_exit_status\n\
+             }}\n\
+             if [[ \";${{PROMPT_COMMAND[*]:-}};\" != *\";_rpilot_hook;\"* ]]; then\n  \
+                 PROMPT_COMMAND=\"_rpilot_hook${{PROMPT_COMMAND:+;$PROMPT_COMMAND}}\"\n\
+             fi\n",
+            exe
+        ),
+    }
+}
+
+/// Appends the commands which unload the previous project and load the project of the
+/// current directory to `commands`. Nothing is added while the shell stays in the same
+/// project. The commands which unload the previous project are added before the current
+/// one is read, so they are kept when it fails to load.
+fn eval(shell: Shell, commands: &mut String) -> Result<(), Error> {
+    let state = env::var(STATE_VARIABLE)
+        .ok()
+        .and_then(|v| serde_json::from_str::<State>(&v).ok());
+    let root = common::find_project_root(&env::current_dir()?);
+    if let (Some(state), Some(root)) = (&state, &root) {
+        if state.dir == *root {
+            return Ok(());
+        }
+    }
+
+    let mut previous = BTreeMap::new();
+    if let Some(state) = state {
+        commands.push_str(&restore_commands(shell, &state.previous));
+        commands.push_str(&export::unset_commands(shell, &[STATE_VARIABLE]));
+        debug!("Unloaded {}", state.profile);
+        previous = state.previous;
+    }
+    if let Some(root) = root {
+        if let Some(loaded) = load(&root)? {
+            let state = State {
+                dir: root,
+                profile: loaded.profile,
+                previous: loaded
+                    .variables
+                    .iter()
+                    .map(|(k, _)| {
+                        let value = match previous.get(k) {
+                            Some(v) => v.clone(),
+                            None => env::var(k).ok(),
+                        };
+                        (k.clone(), value)
+                    })
+                    .collect(),
+            };
+            let variables = loaded
+                .variables
+                .iter()
+                .map(|(k, v)| (k.as_str(), v.as_str()))
+                .collect::<Vec<(&str, &str)>>();
+            commands.push_str(&export::export_commands(shell, &variables));
+            let state_json = serde_json::to_string(&state).map_err(std::io::Error::from)?;
+            commands.push_str(&export::export_commands(
+                shell,
+                &[(STATE_VARIABLE, &state_json)],
+            ));
+            debug!("Loaded {}", state.profile);
+        }
+    }
+    Ok(())
+}
+
+/// Reads the current profile of the project at `root` when the project was allowed. The
+/// passphrase of an encrypted project is never asked for, so it is only loaded when
+/// `RPILOT_PASSPHRASE` is set.
+fn load(root: &Path) -> Result<Option<Loaded>, Error> {
+    let project_dir = common::get_data_dir()?;
+    let project_id = match common::get_project_id(root) {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+    if !allow::is_allowed(&project_dir, &project_id, root)? {
+        info!(
+            "Skipped {} because it is not allowed. Run `rpilot allow` there to load it",
+            root.display()
+        );
+        return Ok(None);
+    }
+    if common::is_project_encrypted(&project_dir, &project_id)
+        && env::var_os(common::PASSPHRASE_ENV).is_none()
+    {
+        debug!(
+            "Skipped {} because it is encrypted and {} is not set",
+            root.display(),
+            common::PASSPHRASE_ENV
+        );
+        return Ok(None);
+    }
+
+    let cipher = common::load_cipher(&project_dir, &project_id).map_err(Error::Encryption)?;
+    let (_, project) = common::read_config(&project_dir, &project_id, cipher.as_ref())
+        .map_err(Error::CorruptConfig)?;
+    let name = match (*project.current_profile).as_ref() {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+    let profile = common::select_profile(&project, name).map_err(|_| Error::NotExists)?;
+    let env = common::materialize(
+        &project_dir,
+        &project_id,
+        &project,
+        &profile.id,
+        cipher.as_ref(),
+    )?;
+    let env = common::EnvFile::parse(&env)?;
+    let variables = export::exportable_variables(&env)
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+    Ok(Some(Loaded {
+        profile: name.clone(),
+        variables,
+    }))
+}
+
+/// Puts back the values which the loaded variables had before they were loaded
+fn restore_commands(shell: Shell, previous: &BTreeMap<String, Option<String>>) -> String {
+    let mut commands = String::new();
+    for (key, value) in previous {
+        match value {
+            Some(v) => commands.push_str(&export::export_commands(shell, &[(key, v)])),
+            None => commands.push_str(&export::unset_commands(shell, &[key])),
+        }
+    }
+    commands
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_restore_commands() {
+        let mut previous = BTreeMap::new();
+        previous.insert("A".to_string(), None);
+        previous.insert("PATH".to_string(), Some("/bin".to_string()));
+        assert_eq!(
+            restore_commands(Shell::Bash, &previous),
+            "unset A;\nexport PATH='/bin';\n"
+        );
+        assert_eq!(
+            restore_commands(Shell::Fish, &previous),
+            "set -e A;\nset -gx PATH '/bin';\n"
+        );
+    }
+
+    #[test]
+    fn test_state_round_trip() {
+        let mut previous = BTreeMap::new();
+        previous.insert("A".to_string(), Some("it's".to_string()));
+        let state = State {
+            dir: PathBuf::from("/src/project"),
+            profile: "dev".to_string(),
+            previous,
+        };
+        let json = serde_json::to_string(&state).unwrap();
+        assert_eq!(serde_json::from_str::<State>(&json).unwrap(), state);
+    }
+}
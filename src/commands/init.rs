@@ -1,53 +1,89 @@
-use log::{debug, error, info};
+use log::{debug, info, warn};
 use std::env;
 use std::fs;
 use std::io::Result as SimpleResult;
+use std::os::unix::fs::MetadataExt;
 use std::path::{Path, PathBuf};
 use std::result::Result;
-use thiserror::Error;
+use structopt::StructOpt;
 use uuid::Uuid;
 
 use crate::common;
+use crate::error::Error;
 
-#[derive(Error, Debug)]
-enum InitCommandError {
-    #[error("this directory is already initialized for rpilot")]
-    AlreadyInitialized,
-
-    #[error("external library failed")]
-    ExternalFail(#[from] std::io::Error),
-}
-
-pub fn execute() {
-    match _execute() {
-        Ok(_) => info!("Successfully initialized rpilot for this directory"),
-        Err(e) => error!("{}", e),
-    }
+#[derive(Debug, PartialEq, StructOpt)]
+pub struct Args {
+    /// Encrypt the stored profiles with a passphrase. Can also be used on an initialized project.
+    #[structopt(long)]
+    encrypt: bool,
 }
 
-fn _execute() -> Result<(), InitCommandError> {
+pub fn execute(args: &Args) -> Result<(), Error> {
     let pwd = env::current_dir()?;
     let project_id = common::get_project_id(&pwd);
+    let data_dir = common::get_data_dir()?;
 
-    if project_id.is_some() {
-        return Err(InitCommandError::AlreadyInitialized);
+    if let Some(id) = project_id {
+        if args.encrypt {
+            return encrypt_project(&data_dir, &id);
+        }
+        return Err(Error::AlreadyInitialized);
+    }
+    if args.encrypt {
+        // The project of a parent directory is meant here, not a new nested one
+        if let Some(root) = common::find_project_root(&pwd) {
+            warn!(
+                "{} is already a project. Run `rpilot -C {} init --encrypt` to encrypt it",
+                root.display(),
+                root.display()
+            );
+            return Err(Error::AlreadyInitialized);
+        }
     }
 
     debug!("Generating a new rpilot id");
-    let (path, id) = create_env_dir()?;
+    let (path, id) = create_env_dir(&data_dir)?;
     info!("{}", format!("Generated a new rpilot id<{}>", &id));
 
     if let Some(p) = pwd.to_str() {
         debug!("{}", format!("Creating .rpilot at {}", &p));
     }
-    write_id(id, &pwd)?;
+    write_id(id.clone(), &pwd)?;
     write_config_file(&path)?;
+    common::record_checkout(&data_dir, &id, &pwd)?;
+
+    if args.encrypt {
+        encrypt_project(&data_dir, &id)?;
+    }
+    info!("Successfully initialized rpilot for this directory");
     Ok(())
 }
 
-fn create_env_dir() -> Result<(PathBuf, String), InitCommandError> {
+fn encrypt_project(project_dir: &Path, id: &str) -> Result<(), Error> {
+    let _lock = common::lock_project(project_dir, id)?;
+    if common::is_project_encrypted(project_dir, id) {
+        return Err(Error::AlreadyEncrypted);
+    }
+
+    let passphrase =
+        common::read_new_passphrase(common::PASSPHRASE_ENV).map_err(Error::Encryption)?;
+    // A hard link is detached from the profile once the profile is encrypted
+    let linked = fs::symlink_metadata(env::current_dir()?.join(".env"))
+        .map(|m| m.file_type().is_symlink() || m.nlink() > 1)
+        .unwrap_or(false);
+    common::change_passphrase(project_dir, id, None, &passphrase)?;
+    info!("Encrypted the profiles of this project");
+
+    if linked {
+        warn!(
+            "The current .env was linked to a profile which is encrypted now. Please apply the profile again."
+        );
+    }
+    Ok(())
+}
+
+fn create_env_dir(data_dir: &Path) -> Result<(PathBuf, String), Error> {
     let id = Uuid::new_v4();
-    let data_dir = common::get_data_dir()?;
     let path = data_dir.join(id.to_string());
     fs::create_dir_all(&path)?;
     Ok((path, id.to_string()))
@@ -55,12 +91,12 @@ fn create_env_dir() -> Result<(PathBuf, String), InitCommandError> {
 
 fn write_id(id: String, current_dir: &Path) -> SimpleResult<()> {
     let config = current_dir.join(common::ID_FILENAME);
-    fs::write(config, id)
+    common::write_atomic(&config, id.as_bytes())
 }
 
-fn write_config_file(path: &Path) -> SimpleResult<fs::File> {
+fn write_config_file(path: &Path) -> SimpleResult<()> {
     let config = path.join(common::CONFIG_FILENAME);
-    fs::File::create(config)
+    common::save_config(&common::Project::default(), &config, None)
 }
 
 #[cfg(test)]
@@ -71,7 +107,10 @@ mod test {
 
     #[test]
     fn test_create_env_dir() {
-        let (env_dir, id) = create_env_dir().unwrap();
+        let tmp_dir = TempDir::new("test_create_env_dir").unwrap();
+        let data_dir = tmp_dir.path().join("rp");
+        let (env_dir, id) = create_env_dir(&data_dir).unwrap();
+        assert!(env_dir.is_dir());
         let target = env_dir.to_str().unwrap().to_owned();
         let re = Regex::new(r"rp/[0-9a-zA-Z-]*$").unwrap();
         assert_eq!(target.is_empty(), false);
@@ -91,5 +130,6 @@ mod test {
         let tmp_dir = TempDir::new("test_write_config").unwrap();
         let tmp_dir_path = tmp_dir.path().to_owned();
         assert_eq!(write_config_file(&tmp_dir_path).is_ok(), true);
+        assert!(common::read_config(&tmp_dir_path, "", None).is_ok());
     }
 }
@@ -1,8 +1,29 @@
 pub mod add;
+pub mod allow;
 pub mod apply;
+pub mod check;
+pub mod clone;
+pub mod config;
 pub mod current;
+pub mod diff;
 pub mod edit;
+pub mod example;
+pub mod export;
+pub mod gc;
+pub mod get;
+pub mod history;
+pub mod hook;
 pub mod init;
 pub mod list;
+pub mod protect;
 pub mod remove;
+pub mod rename;
+pub mod restore_backup;
+pub mod rollback;
+pub mod rotate_key;
+pub mod run;
+pub mod set;
 pub mod show;
+pub mod status;
+pub mod unset;
+pub mod verify;
@@ -0,0 +1,44 @@
+use log::info;
+use std::result::Result;
+use structopt::StructOpt;
+
+use crate::common;
+use crate::error::Error;
+
+#[derive(Debug, PartialEq, StructOpt)]
+pub struct Args {
+    #[structopt(short, long)]
+    name: String,
+
+    /// Remove the protection from the profile
+    #[structopt(long)]
+    off: bool,
+}
+
+pub fn execute(args: &Args) -> Result<(), Error> {
+    let common::Workspace {
+        project_dir,
+        project_id,
+        ..
+    } = common::open_workspace()?;
+    let _lock = common::lock_project(&project_dir, &project_id)?;
+    let cipher = common::load_cipher(&project_dir, &project_id).map_err(Error::Encryption)?;
+    let (config_path, mut project) =
+        common::read_config(&project_dir, &project_id, cipher.as_ref())
+            .map_err(Error::CorruptConfig)?;
+
+    let profile = project
+        .entries
+        .iter_mut()
+        .find(|entry| entry.name == args.name)
+        .ok_or(Error::NotExists)?;
+    profile.protected = !args.off;
+
+    common::save_config(&project, &config_path, cipher.as_ref())?;
+    if args.off {
+        info!("{} is not protected anymore", args.name);
+    } else {
+        info!("{} is protected now", args.name);
+    }
+    Ok(())
+}
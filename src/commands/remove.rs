@@ -1,10 +1,9 @@
-use log::{error, info};
-use std::env;
+use log::info;
 use std::result::Result;
 use structopt::StructOpt;
-use thiserror::Error;
 
 use crate::common;
+use crate::error::Error;
 
 #[derive(Debug, PartialEq, StructOpt)]
 pub struct Args {
@@ -12,61 +11,39 @@ pub struct Args {
     name: String,
 }
 
-#[derive(Error, Debug)]
-enum RemoveCommandError {
-    #[error("reading .rpilot file failed. Make sure this project is initialised properly.")]
-    NotInitialized,
+pub fn execute(args: &Args) -> Result<(), Error> {
+    let common::Workspace {
+        project_dir,
+        project_id,
+        ..
+    } = common::open_workspace()?;
+    let _lock = common::lock_project(&project_dir, &project_id)?;
 
-    #[error("the specified profile name does not exists for this project. Please make sure that you are passing the correvt name.")]
-    NotExists,
-
-    #[error("failed at updating config")]
-    SaveFileError,
-
-    #[error("Failed at reading the config")]
-    ConfigReadError,
-
-    #[error("external library failed")]
-    ExternalFail(#[from] std::io::Error),
-}
-
-pub fn execute(args: &Args) {
-    match _execute(args) {
-        Ok(_) => {
-            info!("successfully removed a profile");
-        }
-        Err(e) => {
-            error!("{}", e);
-        }
-    }
-}
-
-fn _execute(args: &Args) -> Result<(), RemoveCommandError> {
-    let pwd = env::current_dir()?;
-    let project_dir = common::get_data_dir()?;
-    let project_id = common::get_project_id(&pwd);
-
-    if project_id.is_none() {
-        return Err(RemoveCommandError::NotInitialized);
-    }
-
-    let project_id = project_id.unwrap();
-
-    let (mut config_path, mut project) = common::read_config(&project_dir, &project_id)
-        .map_err(|_| RemoveCommandError::ConfigReadError)?;
+    let cipher = common::load_cipher(&project_dir, &project_id).map_err(Error::Encryption)?;
+    let (config_path, mut project) =
+        common::read_config(&project_dir, &project_id, cipher.as_ref())
+            .map_err(Error::CorruptConfig)?;
 
     remove_profile(&mut project, &args.name)?;
 
-    common::save_config(&project, &mut config_path)
-        .map_err(|_| RemoveCommandError::SaveFileError)?;
+    common::save_config(&project, &config_path, cipher.as_ref())?;
 
+    info!("successfully removed a profile");
     Ok(())
 }
 
-fn remove_profile(project: &mut common::Project, name: &str) -> Result<(), RemoveCommandError> {
+fn remove_profile(project: &mut common::Project, name: &str) -> Result<(), Error> {
     info!("removing the profile {}", name);
     match project.entries.iter().position(|entry| entry.name == name) {
         Some(ind) => {
+            let id = &project.entries[ind].id;
+            if let Some(child) = project.entries.iter().find(|v| v.bases.contains(id)) {
+                return Err(Error::InvalidArgument(format!(
+                    "{} can not be removed because {} inherits from it",
+                    name, child.name
+                )));
+            }
+
             project.entries.remove(ind);
 
             if project
@@ -76,11 +53,11 @@ fn remove_profile(project: &mut common::Project, name: &str) -> Result<(), Remov
                 .unwrap_or(&"".to_string())
                 == name
             {
-                project.current_profile = Box::new(None)
+                *project.current_profile = None
             }
             Ok(())
         }
-        None => Err(RemoveCommandError::NotExists),
+        None => Err(Error::NotExists),
     }
 }
 
@@ -97,14 +74,17 @@ mod test {
                     name: "first".to_string(),
                     hash: "test".to_string(),
                     id: "test".to_string(),
+                    ..Default::default()
                 },
                 common::Entry {
                     name: "second".to_string(),
                     hash: "test".to_string(),
                     id: "test".to_string(),
+                    ..Default::default()
                 },
             ],
             current_profile: Box::new(Some("first".to_string())),
+            ..Default::default()
         };
 
         remove_profile(&mut project, "first").unwrap();
@@ -122,16 +102,47 @@ mod test {
                     name: "first".to_string(),
                     hash: "test".to_string(),
                     id: "test".to_string(),
+                    ..Default::default()
                 },
                 common::Entry {
                     name: "second".to_string(),
                     hash: "test".to_string(),
                     id: "test".to_string(),
+                    ..Default::default()
                 },
             ],
             current_profile: Box::new(None),
+            ..Default::default()
         };
 
         assert_eq!(remove_profile(&mut project, "test").is_err(), true);
     }
+
+    #[test]
+    fn test_remove_base_profile_throws_error() {
+        let mut project = common::Project {
+            entries: vec![
+                common::Entry {
+                    name: "common".to_string(),
+                    id: "common-id".to_string(),
+                    ..Default::default()
+                },
+                common::Entry {
+                    name: "dev".to_string(),
+                    id: "dev-id".to_string(),
+                    bases: vec!["common-id".to_string()],
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+
+        assert!(matches!(
+            remove_profile(&mut project, "common"),
+            Err(Error::InvalidArgument(_))
+        ));
+        assert_eq!(project.entries.len(), 2);
+        remove_profile(&mut project, "dev").unwrap();
+        remove_profile(&mut project, "common").unwrap();
+    }
 }
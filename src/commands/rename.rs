@@ -0,0 +1,96 @@
+use log::info;
+use std::result::Result;
+use structopt::StructOpt;
+
+use crate::common;
+use crate::error::Error;
+
+#[derive(Debug, PartialEq, StructOpt)]
+pub struct Args {
+    /// Current name of the profile
+    old: String,
+
+    /// New name of the profile
+    new: String,
+}
+
+pub fn execute(args: &Args) -> Result<(), Error> {
+    let common::Workspace {
+        project_dir,
+        project_id,
+        ..
+    } = common::open_workspace()?;
+    let _lock = common::lock_project(&project_dir, &project_id)?;
+    let cipher = common::load_cipher(&project_dir, &project_id).map_err(Error::Encryption)?;
+    let (config_path, mut project) =
+        common::read_config(&project_dir, &project_id, cipher.as_ref())
+            .map_err(Error::CorruptConfig)?;
+
+    rename_profile(&mut project, &args.old, &args.new)?;
+
+    common::save_config(&project, &config_path, cipher.as_ref())?;
+    info!("Successfully renamed {} to {}", args.old, args.new);
+    Ok(())
+}
+
+/// Renames the entry and the current profile together, so that both are saved with a single
+/// write of the config
+fn rename_profile(project: &mut common::Project, old: &str, new: &str) -> Result<(), Error> {
+    if new.is_empty() {
+        return Err(Error::InvalidArgument(
+            "the name of a profile must not be empty".to_string(),
+        ));
+    }
+    if project.entries.iter().any(|entry| entry.name == new) {
+        return Err(Error::AlreadyExists);
+    }
+
+    let profile = project
+        .entries
+        .iter_mut()
+        .find(|entry| entry.name == old)
+        .ok_or(Error::NotExists)?;
+    profile.name = new.to_string();
+
+    if (*project.current_profile).as_deref() == Some(old) {
+        *project.current_profile = Some(new.to_string());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rename_profile() {
+        let mut project = common::Project {
+            entries: vec![
+                common::Entry {
+                    name: "dev".to_string(),
+                    id: "dev id".to_string(),
+                    protected: true,
+                    ..Default::default()
+                },
+                common::Entry {
+                    name: "prod".to_string(),
+                    id: "prod id".to_string(),
+                    ..Default::default()
+                },
+            ],
+            current_profile: Box::new(Some("dev".to_string())),
+            ..Default::default()
+        };
+
+        rename_profile(&mut project, "dev", "local").unwrap();
+        assert_eq!(project.entries[0].name, "local");
+        assert_eq!(project.entries[0].id, "dev id");
+        assert!(project.entries[0].protected);
+        assert_eq!(*project.current_profile, Some("local".to_string()));
+
+        let result = rename_profile(&mut project, "local", "prod");
+        assert!(matches!(result, Err(Error::AlreadyExists)));
+        let result = rename_profile(&mut project, "dev", "test");
+        assert!(matches!(result, Err(Error::NotExists)));
+    }
+}
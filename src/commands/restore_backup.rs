@@ -0,0 +1,74 @@
+use log::{debug, info};
+use std::fs;
+use std::result::Result;
+use structopt::StructOpt;
+
+use crate::common;
+use crate::error::Error;
+
+#[derive(Debug, PartialEq, StructOpt)]
+pub struct Args {
+    /// Id of the backup to restore. The latest backup is restored when it is omitted.
+    #[structopt(long)]
+    id: Option<String>,
+
+    /// List the available backups instead of restoring one
+    #[structopt(long)]
+    list: bool,
+}
+
+pub fn execute(args: &Args) -> Result<(), Error> {
+    let common::Workspace {
+        pwd,
+        project_dir,
+        project_id,
+    } = common::open_workspace()?;
+    let _lock = common::lock_project(&project_dir, &project_id)?;
+    let backups = common::list_backups(&project_dir, &project_id)?;
+
+    if args.list {
+        print_backups(&backups);
+        return Ok(());
+    }
+
+    let backup_id = match &args.id {
+        Some(v) => backups.iter().find(|id| *id == v),
+        None => backups.last(),
+    }
+    .ok_or(Error::NoBackup)?;
+
+    let cipher = common::load_cipher(&project_dir, &project_id).map_err(Error::Encryption)?;
+    let (config_path, mut project) =
+        common::read_config(&project_dir, &project_id, cipher.as_ref())
+            .map_err(Error::CorruptConfig)?;
+    let (backup_path, backup) =
+        common::read_backup(&project_dir, &project_id, backup_id, cipher.as_ref())
+            .map_err(Error::Encryption)?;
+    let backup = backup.ok_or(Error::NoBackup)?;
+
+    let current_env_path = pwd.join(".env");
+    if let Ok(metadata) = fs::symlink_metadata(&current_env_path) {
+        if metadata.file_type().is_file() {
+            let current_env = fs::read_to_string(&current_env_path)?;
+            let id = common::backup_env(&project_dir, &project_id, &current_env, cipher.as_ref())?;
+            info!("Backed up the current .env as {}", id);
+        }
+    }
+
+    common::write_private_file(&current_env_path, &backup)?;
+    fs::remove_file(backup_path)?;
+    debug!("Removed the backup {}", backup_id);
+
+    *project.current_profile = None;
+    project.applied_hash.clear();
+    common::save_config(&project, &config_path, cipher.as_ref())?;
+    info!("Successfully restored the backup {}", backup_id);
+    Ok(())
+}
+
+fn print_backups(backups: &[String]) {
+    println!("Here are the list of the backups of .env for this project");
+    for backup in backups {
+        println!("* {}", backup);
+    }
+}
@@ -0,0 +1,75 @@
+use log::info;
+use std::result::Result;
+use structopt::StructOpt;
+
+use crate::commands::apply;
+use crate::common;
+use crate::error::Error;
+
+#[derive(Debug, PartialEq, StructOpt)]
+pub struct Args {
+    #[structopt(short, long)]
+    name: String,
+
+    /// Number of the revision to restore. See history command.
+    #[structopt(long)]
+    to: u32,
+
+    /// Describe the change in the history of the profile
+    #[structopt(short, long)]
+    message: Option<String>,
+}
+
+pub fn execute(args: &Args) -> Result<(), Error> {
+    let common::Workspace {
+        pwd,
+        project_dir,
+        project_id,
+    } = common::open_workspace()?;
+    let _lock = common::lock_project(&project_dir, &project_id)?;
+    let cipher = common::load_cipher(&project_dir, &project_id).map_err(Error::Encryption)?;
+    let (config_path, mut project) =
+        common::read_config(&project_dir, &project_id, cipher.as_ref())
+            .map_err(Error::CorruptConfig)?;
+
+    let profile = common::select_profile(&project, &args.name).map_err(|_| Error::NotExists)?;
+    let env = common::read_revision(
+        &project_dir,
+        &project_id,
+        &project,
+        profile,
+        args.to,
+        cipher.as_ref(),
+    )?;
+
+    // The current content is kept too, so that the rollback can be undone
+    let message = match &args.message {
+        Some(v) => v.clone(),
+        None => format!("rollback to {}", args.to),
+    };
+    common::record_revision(
+        &project_dir,
+        &project_id,
+        &mut project,
+        &args.name,
+        &message,
+    )?;
+    common::write_profile(
+        &project_dir,
+        &project_id,
+        &mut project,
+        &args.name,
+        &env,
+        cipher.as_ref(),
+    )?;
+    apply::refresh_applied_copy(
+        &pwd,
+        &project_dir,
+        &project_id,
+        &mut project,
+        cipher.as_ref(),
+    )?;
+    common::save_config(&project, &config_path, cipher.as_ref())?;
+    info!("Rolled back {} to the revision {}", args.name, args.to);
+    Ok(())
+}
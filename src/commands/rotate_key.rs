@@ -0,0 +1,23 @@
+use log::info;
+use std::result::Result;
+
+use crate::common;
+use crate::error::Error;
+
+pub fn execute() -> Result<(), Error> {
+    let common::Workspace {
+        project_dir,
+        project_id,
+        ..
+    } = common::open_workspace()?;
+    let _lock = common::lock_project(&project_dir, &project_id)?;
+    let cipher = common::load_cipher(&project_dir, &project_id)
+        .map_err(Error::Encryption)?
+        .ok_or(Error::NotEncrypted)?;
+
+    let passphrase =
+        common::read_new_passphrase(common::NEW_PASSPHRASE_ENV).map_err(Error::Encryption)?;
+    common::change_passphrase(&project_dir, &project_id, Some(&cipher), &passphrase)?;
+    info!("Successfully changed the passphrase of this project");
+    Ok(())
+}
@@ -0,0 +1,101 @@
+use std::env;
+use std::os::unix::process::CommandExt;
+use std::process::Command;
+use std::result::Result;
+use structopt::StructOpt;
+
+use crate::common;
+use crate::error::Error;
+
+#[derive(Debug, PartialEq, StructOpt)]
+pub struct Args {
+    #[structopt(short, long)]
+    name: String,
+
+    /// Start the command from an empty environment instead of the current one
+    #[structopt(long)]
+    isolate: bool,
+
+    /// Let the profile win over variables which are already set (default)
+    #[structopt(long = "override", overrides_with = "no-override")]
+    override_existing: bool,
+
+    /// Keep the value of variables which are already set
+    #[structopt(long = "no-override", overrides_with = "override")]
+    no_override: bool,
+
+    /// The command to run and its arguments, after `--`
+    #[structopt(required = true, last = true)]
+    command: Vec<String>,
+}
+
+pub fn execute(args: &Args) -> Result<(), Error> {
+    let common::Workspace {
+        project_dir,
+        project_id,
+        ..
+    } = common::open_workspace()?;
+    let cipher = common::load_cipher(&project_dir, &project_id).map_err(Error::Encryption)?;
+    let (_, project) = common::read_config(&project_dir, &project_id, cipher.as_ref())
+        .map_err(Error::CorruptConfig)?;
+
+    let profile = common::select_profile(&project, &args.name).map_err(|_| Error::NotExists)?;
+
+    // The same content as `apply` writes, so that `${VAR}` is interpolated
+    let env = common::materialize(
+        &project_dir,
+        &project_id,
+        &project,
+        &profile.id,
+        cipher.as_ref(),
+    )?;
+    let env = common::EnvFile::parse(&env)?;
+
+    let mut command = Command::new(&args.command[0]);
+    command.args(&args.command[1..]);
+    if args.isolate {
+        command.env_clear();
+    }
+
+    let override_existing = args.isolate || !args.no_override;
+    command.envs(variables_to_set(&env, override_existing, |key| {
+        env::var_os(key).is_some()
+    }));
+
+    // exec only returns when the command could not be started. Otherwise the
+    // command replaces this process, so it keeps our pid, receives the signals
+    // sent to rpilot and its exit code is the one seen by the caller.
+    let e = command.exec();
+    Err(Error::ExecFail(args.command[0].clone(), e))
+}
+
+fn variables_to_set<F>(
+    env: &common::EnvFile,
+    override_existing: bool,
+    is_set: F,
+) -> Vec<(&str, &str)>
+where
+    F: Fn(&str) -> bool,
+{
+    env.variables()
+        .filter(|v| override_existing || !is_set(&v.key))
+        .map(|v| (v.key.as_str(), v.value.as_str()))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_variables_to_set() {
+        let env = common::EnvFile::parse("A=1\nB=2").unwrap();
+        let is_set = |key: &str| key == "A";
+
+        assert_eq!(
+            variables_to_set(&env, true, is_set),
+            vec![("A", "1"), ("B", "2")]
+        );
+        assert_eq!(variables_to_set(&env, false, is_set), vec![("B", "2")]);
+    }
+}
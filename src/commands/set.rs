@@ -0,0 +1,133 @@
+use log::info;
+use std::result::Result;
+use structopt::StructOpt;
+
+use crate::commands::apply;
+use crate::common;
+use crate::error::Error;
+
+#[derive(Debug, PartialEq, StructOpt)]
+pub struct Args {
+    #[structopt(short, long)]
+    name: String,
+
+    /// Variables to set, as KEY=VALUE
+    #[structopt(required = true)]
+    assignments: Vec<String>,
+
+    /// Describe the change in the history of the profile
+    #[structopt(short, long)]
+    message: Option<String>,
+}
+
+pub fn execute(args: &Args) -> Result<(), Error> {
+    let assignments = args
+        .assignments
+        .iter()
+        .map(|v| parse_assignment(v))
+        .collect::<Result<Vec<(&str, &str)>, Error>>()?;
+
+    update_profile(&args.name, args.message.as_deref(), |env| {
+        for (key, value) in &assignments {
+            env.set(key, value);
+        }
+        Ok(())
+    })?;
+    info!("Successfully updated the profile {}", args.name);
+    Ok(())
+}
+
+/// Changes the variables of the profile `name` in place, keeping its comments and the order
+/// of its lines. The previous content is kept in the history with `message`, and the hash of
+/// the profile and a `.env` applied as a copy are updated too.
+///
+/// # Errors
+///
+/// Will return `Err` if the profile does not exist or can not be parsed, or if `update` fails
+pub(crate) fn update_profile<F>(name: &str, message: Option<&str>, update: F) -> Result<(), Error>
+where
+    F: FnOnce(&mut common::EnvFile) -> Result<(), Error>,
+{
+    let common::Workspace {
+        pwd,
+        project_dir,
+        project_id,
+    } = common::open_workspace()?;
+    let _lock = common::lock_project(&project_dir, &project_id)?;
+    let cipher = common::load_cipher(&project_dir, &project_id).map_err(Error::Encryption)?;
+    let (config_path, mut project) =
+        common::read_config(&project_dir, &project_id, cipher.as_ref())
+            .map_err(Error::CorruptConfig)?;
+
+    let profile = common::select_profile(&project, name).map_err(|_| Error::NotExists)?;
+    let (_, env) = common::read_env(
+        &project_dir,
+        &project_id,
+        &project,
+        profile,
+        cipher.as_ref(),
+    )?;
+
+    let previous = env.unwrap_or_default();
+    let mut env = common::EnvFile::parse(&previous)?;
+    update(&mut env)?;
+    let env = env.to_string();
+    if env == previous {
+        return Ok(());
+    }
+
+    common::record_revision(
+        &project_dir,
+        &project_id,
+        &mut project,
+        name,
+        message.unwrap_or_default(),
+    )?;
+    common::write_profile(
+        &project_dir,
+        &project_id,
+        &mut project,
+        name,
+        &env,
+        cipher.as_ref(),
+    )?;
+    apply::refresh_applied_copy(
+        &pwd,
+        &project_dir,
+        &project_id,
+        &mut project,
+        cipher.as_ref(),
+    )?;
+    common::save_config(&project, &config_path, cipher.as_ref())?;
+    Ok(())
+}
+
+fn parse_assignment(assignment: &str) -> Result<(&str, &str), Error> {
+    let invalid = || {
+        Error::InvalidArgument(format!(
+            "{} is not a valid assignment. Please use KEY=VALUE",
+            assignment
+        ))
+    };
+    let (key, value) = assignment.split_once('=').ok_or_else(invalid)?;
+    if !common::is_valid_key(key) {
+        return Err(invalid());
+    }
+    Ok((key, value))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_assignment() {
+        assert_eq!(
+            parse_assignment("URL=postgres://a?b=c").unwrap(),
+            ("URL", "postgres://a?b=c")
+        );
+        assert_eq!(parse_assignment("EMPTY=").unwrap(), ("EMPTY", ""));
+        assert!(parse_assignment("NO_VALUE").is_err());
+        assert!(parse_assignment("1KEY=value").is_err());
+    }
+}
@@ -1,61 +1,91 @@
-use log::error;
-use std::env;
 use std::result::Result;
 use structopt::StructOpt;
-use thiserror::Error;
 
 use crate::common;
+use crate::error::Error;
 
 #[derive(Debug, PartialEq, StructOpt)]
 pub struct Args {
     #[structopt(short, long)]
     name: String,
-}
-
-#[derive(Error, Debug)]
-enum ShowCommandError {
-    #[error("reading .rpilot file failed. Make sure this project is initialised properly.")]
-    NotInitialized,
-
-    #[error("the specified profile name does not exists for this project. Please make sure that you are passing the correvt name.")]
-    NotExists,
-
-    #[error("Failed at reading the config")]
-    ConfigReadError,
 
-    #[error("external library failed")]
-    ExternalFail(#[from] std::io::Error),
+    /// Show the profile with its bases merged in, and where each variable comes from
+    #[structopt(long)]
+    resolved: bool,
 }
 
-pub fn execute(args: &Args) {
-    match _execute(args) {
-        Ok(_) => (),
-        Err(e) => {
-            error!("{}", e);
-        }
-    }
-}
+pub fn execute(args: &Args) -> Result<(), Error> {
+    let common::Workspace {
+        project_dir,
+        project_id,
+        ..
+    } = common::open_workspace()?;
+    let cipher = common::load_cipher(&project_dir, &project_id).map_err(Error::Encryption)?;
+    let (_, project) = common::read_config(&project_dir, &project_id, cipher.as_ref())
+        .map_err(Error::CorruptConfig)?;
 
-fn _execute(args: &Args) -> Result<(), ShowCommandError> {
-    let pwd = env::current_dir()?;
-    let project_dir = common::get_data_dir()?;
-    let project_id = common::get_project_id(&pwd);
+    let profile = common::select_profile(&project, &args.name).map_err(|_| Error::NotExists)?;
 
-    if project_id.is_none() {
-        return Err(ShowCommandError::NotInitialized);
+    if args.resolved {
+        let layers = common::read_layers(
+            &project_dir,
+            &project_id,
+            &project,
+            &profile.id,
+            cipher.as_ref(),
+        )?;
+        println!("The resolved content of the env file");
+        println!("------------------------------------");
+        print!("{}", annotate(&layers));
+        return Ok(());
     }
 
-    let project_id = project_id.unwrap();
-    let (_, project) = common::read_config(&project_dir, &project_id)
-        .map_err(|_| ShowCommandError::ConfigReadError)?;
-
-    let profile =
-        common::select_profile(&project, &args.name).map_err(|_| ShowCommandError::NotExists)?;
-
-    let (_, env) = common::read_env(&project_dir, &project_id, &profile.id);
+    let (_, env) = common::read_env(
+        &project_dir,
+        &project_id,
+        &project,
+        profile,
+        cipher.as_ref(),
+    )?;
     println!("The content of the env file");
     println!("---------------------------");
     println!("{}", env.unwrap_or_else(|| "".to_string()));
 
     Ok(())
 }
+
+fn annotate(layers: &[(String, common::EnvFile)]) -> String {
+    common::annotate_layers(layers)
+        .iter()
+        .map(|v| {
+            format!(
+                "{} # from {}\n",
+                common::Variable::new(&v.key, &v.value),
+                v.source
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_annotate() {
+        let layers = vec![
+            (
+                "common".to_string(),
+                common::EnvFile::parse("A=1\nB=2").unwrap(),
+            ),
+            (
+                "dev".to_string(),
+                common::EnvFile::parse("B=two words").unwrap(),
+            ),
+        ];
+        assert_eq!(
+            annotate(&layers),
+            "A=1 # from common\nB=\"two words\" # from dev\n"
+        );
+    }
+}
@@ -0,0 +1,236 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::result::Result;
+use structopt::StructOpt;
+
+use crate::common;
+use crate::error::Error;
+
+#[derive(Debug, PartialEq, StructOpt)]
+pub struct Args {
+    /// Print one line which is easy to parse, for shell prompts: applied NAME, modified NAME, unmanaged or missing
+    #[structopt(long)]
+    porcelain: bool,
+}
+
+/// What the `.env` in the project actually is, regardless of the recorded current profile
+#[derive(Debug, PartialEq)]
+pub(crate) enum EnvState {
+    /// `.env` is the named profile
+    Applied(String),
+    /// `.env` is a copy of the named profile which was edited after it was applied, or a
+    /// link to the profile which was edited in place
+    Modified(String),
+    /// `.env` exists but is not any of the profiles
+    Unmanaged,
+    /// There is no `.env`, or it is a broken link
+    Missing,
+}
+
+pub fn execute(args: &Args) -> Result<(), Error> {
+    let common::Workspace {
+        pwd,
+        project_dir,
+        project_id,
+    } = common::open_workspace()?;
+    let cipher = common::load_cipher(&project_dir, &project_id).map_err(Error::Encryption)?;
+    let (_, project) = common::read_config(&project_dir, &project_id, cipher.as_ref())
+        .map_err(Error::CorruptConfig)?;
+
+    let state = resolve_env(&pwd, &project_dir, &project_id, &project, cipher.as_ref())?;
+    if args.porcelain {
+        println!("{}", porcelain(&state));
+        return Ok(());
+    }
+
+    match &state {
+        EnvState::Applied(name) => println!("applied: {}", name),
+        EnvState::Modified(name) => println!("modified since apply: {}", name),
+        EnvState::Unmanaged => println!("unmanaged file"),
+        EnvState::Missing => println!("missing"),
+    }
+    if let Some(current) = project.current_profile.as_ref() {
+        let matches = match &state {
+            EnvState::Applied(name) | EnvState::Modified(name) => name == current,
+            _ => false,
+        };
+        if !matches {
+            println!("The last applied profile was {}", current);
+        }
+    }
+    Ok(())
+}
+
+fn porcelain(state: &EnvState) -> String {
+    match state {
+        EnvState::Applied(name) => format!("applied {}", name),
+        EnvState::Modified(name) => format!("modified {}", name),
+        EnvState::Unmanaged => "unmanaged".to_string(),
+        EnvState::Missing => "missing".to_string(),
+    }
+}
+
+/// Finds which profile the `.env` in `pwd` is. A symlink is resolved with readlink and a
+/// hard link by its inode, and the linked profile is checked against its recorded hash. A
+/// regular file is compared with the hash recorded when it was applied as a copy, and then
+/// with the content of every profile.
+///
+/// # Errors
+///
+/// Will return `Err` if it fails to read `.env` or the profiles of the project
+pub(crate) fn resolve_env(
+    pwd: &Path,
+    project_dir: &Path,
+    project_id: &str,
+    project: &common::Project,
+    cipher: Option<&common::Cipher>,
+) -> Result<EnvState, Error> {
+    let current_env_path = pwd.join(".env");
+    let metadata = match fs::symlink_metadata(&current_env_path) {
+        Ok(v) => v,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(EnvState::Missing),
+        Err(e) => return Err(e.into()),
+    };
+    let profile_path = |entry: &common::Entry| project_dir.join(project_id).join(&entry.id);
+
+    if metadata.file_type().is_symlink() {
+        if !current_env_path.exists() {
+            return Ok(EnvState::Missing);
+        }
+        let target = fs::read_link(&current_env_path)?;
+        return match project
+            .entries
+            .iter()
+            .find(|entry| profile_path(entry) == target)
+        {
+            Some(entry) => linked_state(project_dir, project_id, project, entry),
+            None => Ok(EnvState::Unmanaged),
+        };
+    }
+    if !metadata.file_type().is_file() {
+        return Ok(EnvState::Unmanaged);
+    }
+
+    for entry in &project.entries {
+        if let Ok(v) = fs::metadata(profile_path(entry)) {
+            if v.dev() == metadata.dev() && v.ino() == metadata.ino() {
+                return linked_state(project_dir, project_id, project, entry);
+            }
+        }
+    }
+
+    let current_env = fs::read(&current_env_path)?;
+    if let Some(current) = project.current_profile.as_ref() {
+        if !project.applied_hash.is_empty() {
+            return if common::hash_content(&current_env) == project.applied_hash {
+                Ok(EnvState::Applied(current.clone()))
+            } else {
+                Ok(EnvState::Modified(current.clone()))
+            };
+        }
+    }
+
+    for entry in &project.entries {
+        let (_, env) = common::read_env(project_dir, project_id, project, entry, cipher)?;
+        if env.as_deref().map(str::as_bytes) == Some(&current_env[..]) {
+            return Ok(EnvState::Applied(entry.name.clone()));
+        }
+    }
+    Ok(EnvState::Unmanaged)
+}
+
+/// A `.env` linked to a profile is the profile itself, so an edit of `.env` shows up as a
+/// stored file which does not match its hash
+fn linked_state(
+    project_dir: &Path,
+    project_id: &str,
+    project: &common::Project,
+    entry: &common::Entry,
+) -> Result<EnvState, Error> {
+    match common::check_env(project_dir, project_id, project, entry)? {
+        common::Integrity::Intact => Ok(EnvState::Applied(entry.name.clone())),
+        _ => Ok(EnvState::Modified(entry.name.clone())),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::os::unix;
+    use tempdir::TempDir;
+
+    fn setup(tmp_dir_path: &Path) -> common::Project {
+        fs::create_dir_all(tmp_dir_path.join("project")).unwrap();
+        fs::write(tmp_dir_path.join("project").join("dev id"), "A=1").unwrap();
+        fs::write(tmp_dir_path.join("project").join("prod id"), "A=2").unwrap();
+        common::Project {
+            entries: vec![
+                common::Entry {
+                    name: "dev".to_string(),
+                    id: "dev id".to_string(),
+                    hash: common::hash_content(b"A=1"),
+                    ..Default::default()
+                },
+                common::Entry {
+                    name: "prod".to_string(),
+                    id: "prod id".to_string(),
+                    hash: common::hash_content(b"A=2"),
+                    ..Default::default()
+                },
+            ],
+            current_profile: Box::new(None),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_resolve_env_links() {
+        let tmp_dir = TempDir::new("test_resolve_env_links").unwrap();
+        let tmp_dir_path = tmp_dir.path().to_owned();
+        let project = setup(&tmp_dir_path);
+        let resolve = || resolve_env(&tmp_dir_path, &tmp_dir_path, "project", &project, None);
+        let env_path = tmp_dir_path.join(".env");
+
+        assert_eq!(resolve().unwrap(), EnvState::Missing);
+
+        unix::fs::symlink(tmp_dir_path.join("project").join("prod id"), &env_path).unwrap();
+        assert_eq!(resolve().unwrap(), EnvState::Applied("prod".to_string()));
+
+        fs::remove_file(&env_path).unwrap();
+        fs::hard_link(tmp_dir_path.join("project").join("dev id"), &env_path).unwrap();
+        assert_eq!(resolve().unwrap(), EnvState::Applied("dev".to_string()));
+        fs::write(&env_path, "A=3").unwrap();
+        assert_eq!(resolve().unwrap(), EnvState::Modified("dev".to_string()));
+
+        fs::remove_file(&env_path).unwrap();
+        unix::fs::symlink(tmp_dir_path.join("removed"), &env_path).unwrap();
+        assert_eq!(resolve().unwrap(), EnvState::Missing);
+    }
+
+    #[test]
+    fn test_resolve_env_files() {
+        let tmp_dir = TempDir::new("test_resolve_env_files").unwrap();
+        let tmp_dir_path = tmp_dir.path().to_owned();
+        let mut project = setup(&tmp_dir_path);
+        let env_path = tmp_dir_path.join(".env");
+
+        fs::write(&env_path, "A=2").unwrap();
+        let state = resolve_env(&tmp_dir_path, &tmp_dir_path, "project", &project, None);
+        assert_eq!(state.unwrap(), EnvState::Applied("prod".to_string()));
+
+        fs::write(&env_path, "A=3").unwrap();
+        let state = resolve_env(&tmp_dir_path, &tmp_dir_path, "project", &project, None);
+        assert_eq!(state.unwrap(), EnvState::Unmanaged);
+
+        *project.current_profile = Some("dev".to_string());
+        project.applied_hash = common::hash_content(b"A=1");
+        let state = resolve_env(&tmp_dir_path, &tmp_dir_path, "project", &project, None);
+        assert_eq!(state.unwrap(), EnvState::Modified("dev".to_string()));
+
+        fs::write(&env_path, "A=1").unwrap();
+        let state = resolve_env(&tmp_dir_path, &tmp_dir_path, "project", &project, None);
+        assert_eq!(state.unwrap(), EnvState::Applied("dev".to_string()));
+    }
+}
@@ -0,0 +1,33 @@
+use log::{info, warn};
+use std::result::Result;
+use structopt::StructOpt;
+
+use crate::commands::set;
+use crate::error::Error;
+
+#[derive(Debug, PartialEq, StructOpt)]
+pub struct Args {
+    #[structopt(short, long)]
+    name: String,
+
+    /// Names of the variables to remove
+    #[structopt(required = true)]
+    keys: Vec<String>,
+
+    /// Describe the change in the history of the profile
+    #[structopt(short, long)]
+    message: Option<String>,
+}
+
+pub fn execute(args: &Args) -> Result<(), Error> {
+    set::update_profile(&args.name, args.message.as_deref(), |env| {
+        for key in &args.keys {
+            if !env.unset(key) {
+                warn!("{} is not set in the profile {}", key, args.name);
+            }
+        }
+        Ok(())
+    })?;
+    info!("Successfully updated the profile {}", args.name);
+    Ok(())
+}
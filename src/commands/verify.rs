@@ -0,0 +1,292 @@
+use log::info;
+use std::fs;
+use std::path::Path;
+use std::result::Result;
+use structopt::StructOpt;
+
+use crate::common;
+use crate::common::Integrity;
+use crate::error::Error;
+
+#[derive(Debug, PartialEq, StructOpt)]
+pub struct Args {
+    /// Trust the current content of the profiles which were modified outside rpilot
+    #[structopt(long)]
+    accept: bool,
+
+    /// Protect the profiles with an HMAC keyed per project instead of a plain SHA-256. The key
+    /// is stored out of the data dir.
+    #[structopt(long)]
+    hmac: bool,
+}
+
+pub fn execute(args: &Args) -> Result<(), Error> {
+    let common::Workspace {
+        project_dir,
+        project_id,
+        ..
+    } = common::open_workspace()?;
+    let _lock = if args.accept || args.hmac {
+        Some(common::lock_project(&project_dir, &project_id)?)
+    } else {
+        None
+    };
+    let cipher = common::load_cipher(&project_dir, &project_id).map_err(Error::Encryption)?;
+    let (config_path, mut project) =
+        common::read_config(&project_dir, &project_id, cipher.as_ref())
+            .map_err(Error::CorruptConfig)?;
+
+    let results = verify_profiles(&project_dir, &project_id, &project)?;
+    for (name, integrity) in &results {
+        print_integrity(name, integrity);
+    }
+    // Only the revisions with a problem are listed, as there can be many of them
+    let revisions = verify_revisions(&project_dir, &project_id, &project)?;
+    for (entry, revision, integrity) in &revisions {
+        if *integrity != Integrity::Intact {
+            print_integrity(&revision_label(&project, *entry, *revision), integrity);
+        }
+    }
+
+    let mut changed = false;
+    if args.accept {
+        for (name, _) in results.iter().filter(|(_, v)| *v == Integrity::Modified) {
+            accept_profile(
+                &project_dir,
+                &project_id,
+                &mut project,
+                name,
+                cipher.as_ref(),
+            )?;
+            info!("Accepted the current content of {}", name);
+            changed = true;
+        }
+        for (entry, revision, _) in revisions
+            .iter()
+            .filter(|(_, _, v)| *v == Integrity::Modified)
+        {
+            accept_revision(
+                &project_dir,
+                &project_id,
+                &mut project,
+                *entry,
+                *revision,
+                cipher.as_ref(),
+            )?;
+            info!(
+                "Accepted the current content of {}",
+                revision_label(&project, *entry, *revision)
+            );
+            changed = true;
+        }
+    }
+
+    let mut broken = verify_profiles(&project_dir, &project_id, &project)?
+        .into_iter()
+        .filter(|(_, v)| *v != Integrity::Intact)
+        .map(|(name, _)| name)
+        .collect::<Vec<String>>();
+    for (entry, revision, integrity) in verify_revisions(&project_dir, &project_id, &project)? {
+        if integrity != Integrity::Intact {
+            broken.push(revision_label(&project, entry, revision));
+        }
+    }
+
+    if args.hmac && broken.is_empty() && project.hmac_key.is_empty() {
+        let key_dir = common::get_key_dir()?;
+        enable_hmac(&project_dir, &project_id, &key_dir, &mut project)?;
+        info!("The profiles of this project are protected with an HMAC now");
+        changed = true;
+    }
+    if changed {
+        common::save_config(&project, &config_path, cipher.as_ref())?;
+    }
+
+    if !broken.is_empty() {
+        return Err(Error::Tampered(broken.join(", ")));
+    }
+    Ok(())
+}
+
+fn verify_profiles(
+    project_dir: &Path,
+    project_id: &str,
+    project: &common::Project,
+) -> Result<Vec<(String, Integrity)>, Error> {
+    let mut results = Vec::new();
+    for entry in &project.entries {
+        let integrity = common::check_env(project_dir, project_id, project, entry)?;
+        results.push((entry.name.clone(), integrity));
+    }
+    Ok(results)
+}
+
+/// Returns the integrity of every revision in the history of the profiles, with the indexes
+/// of its profile and of the revision
+fn verify_revisions(
+    project_dir: &Path,
+    project_id: &str,
+    project: &common::Project,
+) -> Result<Vec<(usize, usize, Integrity)>, Error> {
+    let mut results = Vec::new();
+    for (entry_index, entry) in project.entries.iter().enumerate() {
+        for (revision_index, revision) in entry.history.iter().enumerate() {
+            let integrity =
+                common::check_revision(project_dir, project_id, project, entry, revision)?;
+            results.push((entry_index, revision_index, integrity));
+        }
+    }
+    Ok(results)
+}
+
+fn revision_label(project: &common::Project, entry: usize, revision: usize) -> String {
+    let entry = &project.entries[entry];
+    format!("{} revision {}", entry.name, entry.history[revision].number)
+}
+
+fn print_integrity(name: &str, integrity: &Integrity) {
+    match integrity {
+        Integrity::Intact => println!("* {}: ok", name),
+        Integrity::Modified => println!("* {}: modified outside rpilot", name),
+        Integrity::Missing => println!("* {}: missing", name),
+    }
+}
+
+/// Records the hash of the stored file of a profile as it is now. An encrypted profile
+/// must still be decrypted with the key of the project.
+fn accept_profile(
+    project_dir: &Path,
+    project_id: &str,
+    project: &mut common::Project,
+    name: &str,
+    cipher: Option<&common::Cipher>,
+) -> Result<(), Error> {
+    let index = project
+        .entries
+        .iter()
+        .position(|entry| entry.name == name)
+        .ok_or(Error::NotExists)?;
+    let env_path = project_dir
+        .join(project_id)
+        .join(&project.entries[index].id);
+
+    let stored = fs::read(&env_path)?;
+    if let Some(c) = cipher {
+        c.decrypt(&stored).map_err(Error::Encryption)?;
+    }
+    project.entries[index].hash = project.hash_env(&stored);
+    Ok(())
+}
+
+/// Records the hash of the stored file of a revision as it is now, like `accept_profile`
+fn accept_revision(
+    project_dir: &Path,
+    project_id: &str,
+    project: &mut common::Project,
+    entry: usize,
+    revision: usize,
+    cipher: Option<&common::Cipher>,
+) -> Result<(), Error> {
+    let number = project.entries[entry].history[revision].number;
+    let path = common::history_dir(project_dir, project_id, &project.entries[entry].id)
+        .join(number.to_string());
+
+    let stored = fs::read(&path)?;
+    if let Some(c) = cipher {
+        c.decrypt(&stored).map_err(Error::Encryption)?;
+    }
+    project.entries[entry].history[revision].hash = project.hash_env(&stored);
+    Ok(())
+}
+
+/// Generates the HMAC key of the project, stores it in `key_dir` and rehashes the profiles.
+/// The config has to be saved by the caller.
+fn enable_hmac(
+    project_dir: &Path,
+    project_id: &str,
+    key_dir: &Path,
+    project: &mut common::Project,
+) -> Result<(), Error> {
+    project.generate_hmac_key()?;
+    common::write_hmac_key(key_dir, project_id, &project.hmac_key)?;
+    for index in 0..project.entries.len() {
+        let env_path = project_dir
+            .join(project_id)
+            .join(&project.entries[index].id);
+        if let Ok(stored) = fs::read(env_path) {
+            project.entries[index].hash = project.hash_env(&stored);
+        }
+        let history_dir = common::history_dir(project_dir, project_id, &project.entries[index].id);
+        for revision in 0..project.entries[index].history.len() {
+            let number = project.entries[index].history[revision].number;
+            if let Ok(stored) = fs::read(history_dir.join(number.to_string())) {
+                project.entries[index].history[revision].hash = project.hash_env(&stored);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::commands::add;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_verify_and_accept_profiles() {
+        let tmp_dir = TempDir::new("test_verify_profiles").unwrap();
+        let tmp_dir_path = tmp_dir.path().to_owned();
+        fs::create_dir(tmp_dir_path.join("project")).unwrap();
+        let mut project = common::Project::default();
+        add::insert_profile(&tmp_dir_path, "project", &mut project, "dev", "A=1", None).unwrap();
+        add::insert_profile(&tmp_dir_path, "project", &mut project, "prod", "A=2", None).unwrap();
+
+        let dev_path = tmp_dir_path.join("project").join(&project.entries[0].id);
+        fs::write(&dev_path, "A=3").unwrap();
+        fs::remove_file(tmp_dir_path.join("project").join(&project.entries[1].id)).unwrap();
+        let results = verify_profiles(&tmp_dir_path, "project", &project).unwrap();
+        assert_eq!(
+            results,
+            vec![
+                ("dev".to_string(), Integrity::Modified),
+                ("prod".to_string(), Integrity::Missing)
+            ]
+        );
+
+        let read = common::read_env(
+            &tmp_dir_path,
+            "project",
+            &project,
+            &project.entries[0],
+            None,
+        );
+        assert!(matches!(read, Err(Error::Tampered(_))));
+
+        accept_profile(&tmp_dir_path, "project", &mut project, "dev", None).unwrap();
+        let results = verify_profiles(&tmp_dir_path, "project", &project).unwrap();
+        assert_eq!(results[0], ("dev".to_string(), Integrity::Intact));
+    }
+
+    #[test]
+    fn test_enable_hmac() {
+        let tmp_dir = TempDir::new("test_enable_hmac").unwrap();
+        let tmp_dir_path = tmp_dir.path().to_owned();
+        fs::create_dir(tmp_dir_path.join("project")).unwrap();
+        let mut project = common::Project::default();
+        add::insert_profile(&tmp_dir_path, "project", &mut project, "dev", "A=1", None).unwrap();
+        let plain_hash = project.entries[0].hash.clone();
+
+        let key_dir = tmp_dir_path.join("keys");
+        enable_hmac(&tmp_dir_path, "project", &key_dir, &mut project).unwrap();
+        assert!(!project.hmac_key.is_empty());
+        assert_eq!(
+            common::read_hmac_key(&key_dir, "project").unwrap(),
+            Some(project.hmac_key.clone())
+        );
+        assert_ne!(project.entries[0].hash, plain_hash);
+
+        let results = verify_profiles(&tmp_dir_path, "project", &project).unwrap();
+        assert_eq!(results, vec![("dev".to_string(), Integrity::Intact)]);
+    }
+}
@@ -0,0 +1,219 @@
+use std::ffi::OsStr;
+use std::fs;
+use std::io::{Error, ErrorKind, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::result::Result;
+
+/// Replaces `path` with the file which `create` makes at a temporary path next to it. The
+/// rename is atomic, so `path` is never seen half written even when rpilot is interrupted.
+///
+/// # Errors
+///
+/// Will return `Err` if `create` fails or the temporary file can not be renamed to `path`
+pub fn replace_with<F>(path: &Path, create: F) -> Result<(), Error>
+where
+    F: FnOnce(&Path) -> Result<(), Error>,
+{
+    let tmp_path = temp_path(path);
+    // Left behind when a previous rpilot with the same pid was killed
+    let _ = fs::remove_file(&tmp_path);
+    let result = create(&tmp_path).and_then(|_| fs::rename(&tmp_path, path));
+    // The rename does nothing when both are links to the same file, and leaves the
+    // temporary link behind
+    let _ = fs::remove_file(&tmp_path);
+    result?;
+    sync_parent(path)
+}
+
+/// Writes `content` to `path` through a temporary file which is synced before it replaces `path`
+///
+/// # Errors
+///
+/// Will return `Err` if it fails to write or to rename the temporary file
+pub fn write_atomic(path: &Path, content: &[u8]) -> Result<(), Error> {
+    write_with_mode(path, content, 0o666)
+}
+
+/// Writes a file which only the current user can read, for decrypted env values. An
+/// existing file at `path` is replaced.
+///
+/// # Errors
+///
+/// Will return `Err` if it fails to write or to rename the temporary file
+pub fn write_private_file(path: &Path, content: &str) -> Result<(), Error> {
+    write_with_mode(path, content.as_bytes(), 0o600)
+}
+
+fn write_with_mode(path: &Path, content: &[u8], mode: u32) -> Result<(), Error> {
+    replace_with(path, |tmp_path| {
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(mode)
+            .open(tmp_path)?;
+        file.write_all(content)?;
+        file.sync_all()
+    })
+}
+
+/// Replaces several files so that either all of them or none of them get their new content,
+/// even when rpilot is interrupted. The new contents are staged next to the files, then
+/// `journal` records the swap, and only then are the staged files renamed into place. A swap
+/// which was interrupted after the journal was written is completed by `finish_replace`.
+///
+/// # Errors
+///
+/// Will return `Err` if it fails to stage the files or to rename them into place
+pub fn replace_all(journal: &Path, files: &[(PathBuf, Vec<u8>)]) -> Result<(), Error> {
+    finish_replace(journal)?;
+    let mut list = Vec::new();
+    for (path, content) in files {
+        list.extend_from_slice(path.as_os_str().as_bytes());
+        list.push(0);
+        if let Err(e) = stage(path, content) {
+            discard_staged(files);
+            return Err(e);
+        }
+    }
+    if let Err(e) = write_atomic(journal, &list) {
+        discard_staged(files);
+        return Err(e);
+    }
+    finish_replace(journal)
+}
+
+/// Renames the files staged by an interrupted `replace_all` into place. Does nothing when
+/// `journal` does not exist.
+///
+/// # Errors
+///
+/// Will return `Err` if it fails to read the journal or to rename the files
+pub fn finish_replace(journal: &Path) -> Result<(), Error> {
+    let list = match fs::read(journal) {
+        Ok(v) => v,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    for name in list.split(|v| *v == 0).filter(|v| !v.is_empty()) {
+        let path = Path::new(OsStr::from_bytes(name));
+        match fs::rename(staged_path(path), path) {
+            // Renamed before the previous attempt was interrupted
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+            Ok(()) => sync_parent(path)?,
+        }
+    }
+    fs::remove_file(journal)?;
+    sync_parent(journal)
+}
+
+fn stage(path: &Path, content: &[u8]) -> Result<(), Error> {
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(staged_path(path))?;
+    file.write_all(content)?;
+    file.sync_all()
+}
+
+fn discard_staged(files: &[(PathBuf, Vec<u8>)]) {
+    for (path, _) in files {
+        let _ = fs::remove_file(staged_path(path));
+    }
+}
+
+/// The staged file has no pid in its name so that another process can finish the swap
+fn staged_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|v| v.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.staged", name))
+}
+
+fn temp_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|v| v.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.{}.tmp", name, process::id()))
+}
+
+/// Syncs the directory of `path` so that the rename survives a crash
+fn sync_parent(path: &Path) -> Result<(), Error> {
+    let dir = match path.parent() {
+        Some(v) if !v.as_os_str().is_empty() => v,
+        _ => Path::new("."),
+    };
+    fs::File::open(dir)?.sync_all()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_write_atomic() {
+        let tmp_dir = TempDir::new("test_write_atomic").unwrap();
+        let path = tmp_dir.path().join("config");
+        write_atomic(&path, b"first").unwrap();
+        write_atomic(&path, b"second").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "second");
+
+        write_private_file(&path, "secret").unwrap();
+        let metadata = fs::metadata(&path).unwrap();
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+
+        // Only the file itself is left in the directory
+        assert_eq!(fs::read_dir(tmp_dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_replace_with_keeps_the_file_on_failure() {
+        let tmp_dir = TempDir::new("test_replace_with_keeps_the_file_on_failure").unwrap();
+        let path = tmp_dir.path().join("config");
+        write_atomic(&path, b"intact").unwrap();
+
+        let result = replace_with(&path, |tmp_path| {
+            fs::write(tmp_path, "half")?;
+            Err(Error::other("interrupted"))
+        });
+        assert!(result.is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "intact");
+        assert_eq!(fs::read_dir(tmp_dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_replace_all() {
+        let tmp_dir = TempDir::new("test_replace_all").unwrap();
+        let journal = tmp_dir.path().join("journal");
+        let first = tmp_dir.path().join("first");
+        let second = tmp_dir.path().join("second");
+        write_atomic(&first, b"old").unwrap();
+        write_atomic(&second, b"old").unwrap();
+
+        let files = vec![
+            (first.clone(), b"new".to_vec()),
+            (second.clone(), b"new".to_vec()),
+        ];
+        replace_all(&journal, &files).unwrap();
+        assert_eq!(fs::read_to_string(&first).unwrap(), "new");
+        assert_eq!(fs::read_to_string(&second).unwrap(), "new");
+        assert_eq!(fs::read_dir(tmp_dir.path()).unwrap().count(), 2);
+
+        // Interrupted after the first file was renamed
+        fs::write(staged_path(&second), "newer").unwrap();
+        let list = [first.as_os_str().as_bytes(), second.as_os_str().as_bytes()].join(&0);
+        fs::write(&journal, list).unwrap();
+        finish_replace(&journal).unwrap();
+        assert_eq!(fs::read_to_string(&first).unwrap(), "new");
+        assert_eq!(fs::read_to_string(&second).unwrap(), "newer");
+        assert!(!journal.exists());
+    }
+}
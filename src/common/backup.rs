@@ -0,0 +1,142 @@
+use std::fs;
+use std::io::Error;
+use std::path::{Path, PathBuf};
+use std::result::Result;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::{read_env_file, write_env, Cipher};
+
+pub const BACKUPS_DIRNAME: &str = "backups";
+
+fn backups_dir(project_dir: &Path, project_id: &str) -> PathBuf {
+    project_dir.join(project_id).join(BACKUPS_DIRNAME)
+}
+
+/// Stores the content of a `.env` which is not managed by rpilot in the data dir of the
+/// project, encrypted like the profiles. Returns the id of the backup, which is its UTC timestamp.
+///
+/// # Errors
+///
+/// Will return `Err` if it fails to create the backups dir or to write the backup
+pub fn backup_env(
+    project_dir: &Path,
+    project_id: &str,
+    env: &str,
+    cipher: Option<&Cipher>,
+) -> Result<String, Error> {
+    let dir = backups_dir(project_dir, project_id);
+    fs::create_dir_all(&dir)?;
+
+    let timestamp = utc_timestamp(SystemTime::now());
+    let mut id = timestamp.clone();
+    let mut count = 1;
+    while dir.join(&id).exists() {
+        id = format!("{}-{}", timestamp, count);
+        count += 1;
+    }
+
+    write_env(&dir.join(&id), env, cipher)?;
+    Ok(id)
+}
+
+/// Returns the ids of the backups of the project from the oldest to the newest
+///
+/// # Errors
+///
+/// Will return `Err` if it fails to read the backups dir
+pub fn list_backups(project_dir: &Path, project_id: &str) -> Result<Vec<String>, Error> {
+    let dir = backups_dir(project_dir, project_id);
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut ids = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect::<Vec<String>>();
+    ids.sort_by_key(|id| {
+        let (timestamp, count) = id.split_at(id.find('-').unwrap_or(id.len()));
+        (
+            timestamp.to_string(),
+            count.trim_start_matches('-').parse().unwrap_or(0),
+        )
+    });
+    Ok(ids)
+}
+
+/// # Errors
+///
+/// Will return `Err` if the backup of an encrypted project can not be decrypted
+pub fn read_backup(
+    project_dir: &Path,
+    project_id: &str,
+    backup_id: &str,
+    cipher: Option<&Cipher>,
+) -> Result<(PathBuf, Option<String>), Error> {
+    let backup_path = backups_dir(project_dir, project_id).join(backup_id);
+    let env = read_env_file(&backup_path, cipher)?;
+    Ok((backup_path, env))
+}
+
+/// Formats a time as `YYYYMMDDTHHMMSSZ`, which sorts in chronological order
+#[must_use]
+pub fn utc_timestamp(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let (days, secs_of_day) = (secs / 86_400, secs % 86_400);
+
+    // Converts days since 1970-01-01 to a civil date. See http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_utc_timestamp() {
+        assert_eq!(utc_timestamp(UNIX_EPOCH), "19700101T000000Z");
+        let time = UNIX_EPOCH + Duration::from_secs(1_709_210_096);
+        assert_eq!(utc_timestamp(time), "20240229T123456Z");
+    }
+
+    #[test]
+    fn test_backup_env_and_list_backups() {
+        let tmp_dir = TempDir::new("test_backup_env").unwrap();
+        let tmp_dir_path = tmp_dir.path().to_owned();
+        assert!(list_backups(&tmp_dir_path, "project").unwrap().is_empty());
+
+        let first = backup_env(&tmp_dir_path, "project", "A=1", None).unwrap();
+        let second = backup_env(&tmp_dir_path, "project", "A=2", None).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(
+            list_backups(&tmp_dir_path, "project").unwrap(),
+            vec![first, second.clone()]
+        );
+
+        let (_, env) = read_backup(&tmp_dir_path, "project", &second, None).unwrap();
+        assert_eq!(env, Some("A=2".to_string()));
+    }
+}
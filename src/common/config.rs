@@ -0,0 +1,128 @@
+use savefile::load_from_mem;
+use serde::Serialize;
+use serde_json::Value;
+use std::io::{Error, ErrorKind};
+use std::result::Result;
+
+use super::Project;
+
+/// Version of the JSON schema of the config. Bump it whenever the meaning of a field
+/// changes, and convert the configs of the older versions in `decode`. Fields which are
+/// only added do not need a new version because missing fields take their default.
+pub const CONFIG_VERSION: u64 = 1;
+/// Version of the binary savefile layout of `Project` which the released versions stored
+/// configs in before they were JSON. It is only read to migrate them.
+pub const LEGACY_CONFIG_VERSION: u32 = 0;
+/// Copy of the savefile config which the migration to JSON keeps next to the new one
+pub const LEGACY_CONFIG_FILENAME: &str = "config.savefile";
+
+#[derive(Serialize)]
+struct Config<'a> {
+    version: u64,
+    #[serde(flatten)]
+    project: &'a Project,
+}
+
+/// Format a config was stored in
+#[derive(Debug, PartialEq)]
+pub enum Format {
+    Json,
+    /// The savefile format of the previous versions of rpilot
+    Legacy,
+}
+
+/// # Errors
+///
+/// Will return `Err` if the project can not be serialized
+pub fn encode(project: &Project) -> Result<Vec<u8>, Error> {
+    let mut content = serde_json::to_vec_pretty(&Config {
+        version: CONFIG_VERSION,
+        project,
+    })?;
+    content.push(b'\n');
+    Ok(content)
+}
+
+/// Reads a decrypted config in either format. An empty config, which `init` of the
+/// previous versions created, is a project without profiles.
+///
+/// # Errors
+///
+/// Will return `Err` if the config is in neither format, or was written by a newer rpilot
+pub fn decode(content: &[u8]) -> Result<(Project, Format), Error> {
+    let invalid = |message: String| Error::new(ErrorKind::InvalidData, message);
+    match content.iter().find(|v| !v.is_ascii_whitespace()) {
+        None => return Ok((Project::default(), Format::Json)),
+        Some(b'{') => {}
+        Some(_) => {
+            let project = load_from_mem(content, LEGACY_CONFIG_VERSION).map_err(|e| {
+                invalid(format!(
+                    "it is neither JSON nor the format of a previous version ({:?})",
+                    e
+                ))
+            })?;
+            return Ok((project, Format::Legacy));
+        }
+    }
+
+    let value: Value =
+        serde_json::from_slice(content).map_err(|e| invalid(format!("invalid JSON ({})", e)))?;
+    let version = value
+        .get("version")
+        .and_then(Value::as_u64)
+        .ok_or_else(|| invalid("it has no version".to_string()))?;
+    if version > CONFIG_VERSION {
+        return Err(invalid(format!(
+            "it is version {} which needs a newer rpilot",
+            version
+        )));
+    }
+    let project =
+        serde_json::from_value(value).map_err(|e| invalid(format!("invalid field ({})", e)))?;
+    Ok((project, Format::Json))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::common::{ApplyMode, Entry};
+
+    #[test]
+    fn test_encode_and_decode() {
+        let project = Project {
+            entries: vec![Entry {
+                name: "dev".to_string(),
+                id: "id".to_string(),
+                bases: vec!["base id".to_string()],
+                ..Default::default()
+            }],
+            current_profile: Box::new(Some("dev".to_string())),
+            apply_mode: ApplyMode::Copy,
+            ..Default::default()
+        };
+        let content = String::from_utf8(encode(&project).unwrap()).unwrap();
+        assert!(content.contains("\"version\": 1"));
+        assert!(content.contains("\"apply_mode\": \"copy\""));
+
+        let (decoded, format) = decode(content.as_bytes()).unwrap();
+        assert_eq!(format, Format::Json);
+        assert_eq!(decoded.entries[0].bases, project.entries[0].bases);
+        assert_eq!(*decoded.current_profile, Some("dev".to_string()));
+        assert_eq!(decoded.apply_mode, ApplyMode::Copy);
+    }
+
+    #[test]
+    fn test_decode_invalid() {
+        assert_eq!(decode(b"").unwrap().1, Format::Json);
+        assert!(
+            decode(b"{\"version\": 1, \"auto_confirm\": true}")
+                .unwrap()
+                .0
+                .auto_confirm
+        );
+        assert!(decode(b"{\"entries\": []}").is_err());
+        assert!(decode(b"{\"version\": 2}").is_err());
+        assert!(decode(b"{\"version\": 1").is_err());
+        assert!(decode(b"garbage").is_err());
+    }
+}
@@ -0,0 +1,279 @@
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+use std::env;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::num::NonZeroU32;
+use std::path::Path;
+use std::result::Result;
+
+use super::EnvFile;
+
+pub const ENCRYPTION_FILENAME: &str = "encryption";
+pub const PASSPHRASE_ENV: &str = "RPILOT_PASSPHRASE";
+pub const NEW_PASSPHRASE_ENV: &str = "RPILOT_NEW_PASSPHRASE";
+
+const MAGIC: &[u8] = b"RPENC\x01";
+const CHECK_PLAINTEXT: &[u8] = b"rpilot";
+const KEY_LEN: usize = 32;
+const SALT_LEN: usize = 16;
+const DEFAULT_ITERATIONS: u32 = 100_000;
+
+/// AES-256-GCM key derived from the passphrase of a project
+pub struct Cipher {
+    key: LessSafeKey,
+}
+
+/// Content of the `encryption` file stored next to the config of an encrypted project.
+/// It only holds public parameters: the salt and iteration count of PBKDF2 and a
+/// known value encrypted with the derived key to detect a wrong passphrase.
+struct Params {
+    salt: Vec<u8>,
+    iterations: NonZeroU32,
+    check: Vec<u8>,
+}
+
+impl Cipher {
+    fn derive(passphrase: &str, salt: &[u8], iterations: NonZeroU32) -> Cipher {
+        let mut key = [0; KEY_LEN];
+        pbkdf2::derive(
+            pbkdf2::PBKDF2_HMAC_SHA256,
+            iterations,
+            salt,
+            passphrase.as_bytes(),
+            &mut key,
+        );
+        let key = UnboundKey::new(&AES_256_GCM, &key).expect("key length matches AES-256");
+        Cipher {
+            key: LessSafeKey::new(key),
+        }
+    }
+
+    /// # Errors
+    ///
+    /// Will return `Err` if it fails to generate a random nonce or to seal the data
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut nonce = [0; NONCE_LEN];
+        SystemRandom::new()
+            .fill(&mut nonce)
+            .map_err(|_| Error::other("Failed at generating a nonce"))?;
+
+        let mut sealed = plaintext.to_vec();
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::empty(),
+                &mut sealed,
+            )
+            .map_err(|_| Error::other("Failed at encrypting data"))?;
+
+        let mut output = Vec::with_capacity(MAGIC.len() + NONCE_LEN + sealed.len());
+        output.extend_from_slice(MAGIC);
+        output.extend_from_slice(&nonce);
+        output.extend_from_slice(&sealed);
+        Ok(output)
+    }
+
+    /// # Errors
+    ///
+    /// Will return `Err` if the data was not encrypted by rpilot, or if it was encrypted with a different key or modified afterwards
+    pub fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        if !is_encrypted(data) || data.len() < MAGIC.len() + NONCE_LEN {
+            return Err(Error::new(ErrorKind::InvalidData, "data is not encrypted"));
+        }
+        let (nonce, sealed) = data[MAGIC.len()..].split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce)
+            .map_err(|_| Error::new(ErrorKind::InvalidData, "invalid nonce"))?;
+
+        let mut opened = sealed.to_vec();
+        let plaintext_len = self
+            .key
+            .open_in_place(nonce, Aad::empty(), &mut opened)
+            .map_err(|_| Error::new(ErrorKind::InvalidData, "Failed at decrypting data"))?
+            .len();
+        opened.truncate(plaintext_len);
+        Ok(opened)
+    }
+}
+
+impl Params {
+    fn read(path: &Path) -> Result<Params, Error> {
+        let invalid = || Error::new(ErrorKind::InvalidData, "the encryption file is corrupted");
+        let content = fs::read_to_string(path)?;
+        let env = EnvFile::parse(&content).map_err(|_| invalid())?;
+        let field = |key| {
+            env.get(key)
+                .and_then(|v| base64::decode(v).ok())
+                .ok_or_else(invalid)
+        };
+        let iterations = env
+            .get("iterations")
+            .and_then(|v| v.parse().ok())
+            .and_then(NonZeroU32::new)
+            .ok_or_else(invalid)?;
+
+        Ok(Params {
+            salt: field("salt")?,
+            iterations,
+            check: field("check")?,
+        })
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut env = EnvFile::default();
+        env.set("version", "1");
+        env.set("iterations", &self.iterations.to_string());
+        env.set("salt", &base64::encode(&self.salt));
+        env.set("check", &base64::encode(&self.check));
+        format!("{}\n", env).into_bytes()
+    }
+}
+
+#[must_use]
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+#[must_use]
+pub fn is_project_encrypted(project_dir: &Path, id: &str) -> bool {
+    project_dir.join(id).join(ENCRYPTION_FILENAME).exists()
+}
+
+/// Derives the key of an encrypted project. The passphrase is taken from `RPILOT_PASSPHRASE`
+/// when it is set, otherwise it is read from the terminal.
+///
+/// # Errors
+///
+/// Will return `Err` if it fails to read the passphrase or the passphrase is wrong
+pub fn load_cipher(project_dir: &Path, id: &str) -> Result<Option<Cipher>, Error> {
+    let params_path = project_dir.join(id).join(ENCRYPTION_FILENAME);
+    if !params_path.exists() {
+        return Ok(None);
+    }
+
+    let params = Params::read(&params_path)?;
+    let passphrase = read_passphrase(PASSPHRASE_ENV, "Passphrase: ")?;
+    let cipher = Cipher::derive(&passphrase, &params.salt, params.iterations);
+
+    match cipher.decrypt(&params.check) {
+        Ok(v) if v == CHECK_PLAINTEXT => Ok(Some(cipher)),
+        _ => Err(Error::new(
+            ErrorKind::PermissionDenied,
+            "the passphrase is not correct",
+        )),
+    }
+}
+
+/// Derives a key from a new passphrase with a fresh salt and records its parameters
+/// in the project data dir. Data encrypted with the previous key is not touched.
+///
+/// # Errors
+///
+/// Will return `Err` if it fails to generate the salt or to write the encryption file
+pub fn create_cipher(project_dir: &Path, id: &str, passphrase: &str) -> Result<Cipher, Error> {
+    let (cipher, params) = new_cipher(passphrase)?;
+    super::write_atomic(&project_dir.join(id).join(ENCRYPTION_FILENAME), &params)?;
+    Ok(cipher)
+}
+
+/// Derives a key from a new passphrase with a fresh salt. Returns it with the content of
+/// the encryption file which records its parameters, which the caller has to write.
+///
+/// # Errors
+///
+/// Will return `Err` if it fails to generate the salt
+pub fn new_cipher(passphrase: &str) -> Result<(Cipher, Vec<u8>), Error> {
+    let mut salt = vec![0; SALT_LEN];
+    SystemRandom::new()
+        .fill(&mut salt)
+        .map_err(|_| Error::other("Failed at generating a salt"))?;
+    let iterations = NonZeroU32::new(DEFAULT_ITERATIONS).expect("iterations are not zero");
+
+    let cipher = Cipher::derive(passphrase, &salt, iterations);
+    let params = Params {
+        check: cipher.encrypt(CHECK_PLAINTEXT)?,
+        salt,
+        iterations,
+    };
+    Ok((cipher, params.encode()))
+}
+
+/// Reads a new passphrase from `env_var`, or from the terminal asking for it twice
+///
+/// # Errors
+///
+/// Will return `Err` if it fails to read the passphrase, or if it is empty or not confirmed
+pub fn read_new_passphrase(env_var: &str) -> Result<String, Error> {
+    if let Ok(v) = env::var(env_var) {
+        return non_empty(v);
+    }
+    let passphrase = non_empty(rpassword::read_password_from_tty(Some("New passphrase: "))?)?;
+    let confirmation = rpassword::read_password_from_tty(Some("Confirm new passphrase: "))?;
+    if passphrase != confirmation {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "the passphrases do not match",
+        ));
+    }
+    Ok(passphrase)
+}
+
+fn read_passphrase(env_var: &str, prompt: &str) -> Result<String, Error> {
+    match env::var(env_var) {
+        Ok(v) => non_empty(v),
+        Err(_) => non_empty(rpassword::read_password_from_tty(Some(prompt))?),
+    }
+}
+
+fn non_empty(passphrase: String) -> Result<String, Error> {
+    if passphrase.is_empty() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "the passphrase must not be empty",
+        ));
+    }
+    Ok(passphrase)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_encrypt_and_decrypt() {
+        let salt = [1; SALT_LEN];
+        let iterations = NonZeroU32::new(1).unwrap();
+        let cipher = Cipher::derive("secret", &salt, iterations);
+
+        let encrypted = cipher.encrypt(b"KEY=value").unwrap();
+        assert!(is_encrypted(&encrypted));
+        assert_eq!(cipher.decrypt(&encrypted).unwrap(), b"KEY=value");
+
+        let other = Cipher::derive("other", &salt, iterations);
+        assert!(other.decrypt(&encrypted).is_err());
+
+        let mut tampered = encrypted;
+        let last = tampered.len() - 1;
+        tampered[last] ^= 1;
+        assert!(cipher.decrypt(&tampered).is_err());
+    }
+
+    #[test]
+    fn test_create_cipher_and_read_params() {
+        let tmp_dir = TempDir::new("test_create_cipher").unwrap();
+        let tmp_dir_path = tmp_dir.path().to_owned();
+        assert!(!is_project_encrypted(&tmp_dir_path, ""));
+
+        let cipher = create_cipher(&tmp_dir_path, "", "secret").unwrap();
+        assert!(is_project_encrypted(&tmp_dir_path, ""));
+
+        let params = Params::read(&tmp_dir_path.join(ENCRYPTION_FILENAME)).unwrap();
+        let derived = Cipher::derive("secret", &params.salt, params.iterations);
+        assert_eq!(derived.decrypt(&params.check).unwrap(), CHECK_PLAINTEXT);
+
+        let encrypted = cipher.encrypt(b"A=1").unwrap();
+        assert_eq!(derived.decrypt(&encrypted).unwrap(), b"A=1");
+    }
+}
@@ -0,0 +1,572 @@
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// A single `KEY=value` assignment of an env file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variable {
+    pub key: String,
+    pub value: String,
+    pub export: bool,
+    /// Comment after the value with the whitespace before it, kept when the value changes
+    comment: Option<String>,
+    /// Single-quoted values are taken literally and never interpolated
+    literal: bool,
+    raw: Option<String>,
+}
+
+/// One logical line of an env file. A variable with a quoted multiline value
+/// spans several physical lines but is still a single `Line`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Line {
+    Blank(String),
+    Comment(String),
+    Variable(Variable),
+}
+
+/// Parsed representation of an env file which keeps comments, blank lines
+/// and the original formatting so that it can be written back losslessly
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct EnvFile {
+    lines: Vec<Line>,
+    trailing_newline: bool,
+}
+
+#[derive(Error, Debug, PartialEq)]
+#[error("line {line}, column {column}: {message}")]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl Variable {
+    #[must_use]
+    pub fn new(key: &str, value: &str) -> Variable {
+        Variable {
+            key: key.to_string(),
+            value: value.to_string(),
+            export: false,
+            comment: None,
+            literal: false,
+            raw: None,
+        }
+    }
+
+    #[must_use]
+    pub fn is_literal(&self) -> bool {
+        self.literal
+    }
+
+    /// Replaces the value. The line is only reformatted when the value actually changes.
+    pub fn set_value(&mut self, value: &str) {
+        if self.value != value {
+            self.value = value.to_string();
+            self.raw = None;
+        }
+    }
+}
+
+impl EnvFile {
+    /// # Errors
+    ///
+    /// Will return `Err` with the position of the first character that does not form a valid env file
+    pub fn parse(input: &str) -> Result<EnvFile, ParseError> {
+        Parser::new(input).parse()
+    }
+
+    #[must_use]
+    pub fn lines(&self) -> &[Line] {
+        &self.lines
+    }
+
+    pub fn variables(&self) -> impl Iterator<Item = &Variable> {
+        self.lines.iter().filter_map(|line| match line {
+            Line::Variable(v) => Some(v),
+            _ => None,
+        })
+    }
+
+    pub fn variables_mut(&mut self) -> impl Iterator<Item = &mut Variable> {
+        self.lines.iter_mut().filter_map(|line| match line {
+            Line::Variable(v) => Some(v),
+            _ => None,
+        })
+    }
+
+    /// Returns the value of `key`. When the key is assigned more than once, the last assignment wins.
+    #[must_use]
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.variables()
+            .filter(|v| v.key == key)
+            .last()
+            .map(|v| v.value.as_str())
+    }
+
+    /// Updates the last assignment of `key` in place, or appends a new one at the end of the file
+    pub fn set(&mut self, key: &str, value: &str) {
+        let existing = self.lines.iter_mut().rev().find_map(|line| match line {
+            Line::Variable(v) if v.key == key => Some(v),
+            _ => None,
+        });
+
+        match existing {
+            Some(v) => v.set_value(value),
+            None => self.lines.push(Line::Variable(Variable::new(key, value))),
+        }
+    }
+
+    /// Appends the lines of `other` as a layer on top of this file. Variables which `other`
+    /// assigns again are removed from this file, so every key is assigned only once.
+    pub fn layer(&mut self, other: &EnvFile) {
+        for key in other
+            .variables()
+            .map(|v| v.key.clone())
+            .collect::<Vec<String>>()
+        {
+            self.unset(&key);
+        }
+        self.lines.extend(other.lines.iter().cloned());
+        self.trailing_newline = other.trailing_newline;
+    }
+
+    /// Removes every assignment of `key` and returns whether any was found
+    pub fn unset(&mut self, key: &str) -> bool {
+        let before = self.lines.len();
+        self.lines
+            .retain(|line| !matches!(line, Line::Variable(v) if v.key == key));
+        before != self.lines.len()
+    }
+}
+
+/// Returns whether `key` can be used as a variable name: a letter or `_`, followed by
+/// letters, digits, `_` or `.`
+#[must_use]
+pub fn is_valid_key(key: &str) -> bool {
+    let mut chars = key.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => (),
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+impl FromStr for EnvFile {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        EnvFile::parse(s)
+    }
+}
+
+impl fmt::Display for Variable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(raw) = &self.raw {
+            return f.write_str(raw);
+        }
+        if self.export {
+            f.write_str("export ")?;
+        }
+        write!(f, "{}={}", self.key, quote(&self.value))?;
+        if let Some(comment) = &self.comment {
+            f.write_str(comment)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Line::Blank(raw) | Line::Comment(raw) => f.write_str(raw),
+            Line::Variable(v) => v.fmt(f),
+        }
+    }
+}
+
+impl fmt::Display for EnvFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (ind, line) in self.lines.iter().enumerate() {
+            if ind > 0 {
+                f.write_str("\n")?;
+            }
+            line.fmt(f)?;
+        }
+        if self.trailing_newline {
+            f.write_str("\n")?;
+        }
+        Ok(())
+    }
+}
+
+fn is_bare_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "_-./:@%+,=".contains(c)
+}
+
+fn quote(value: &str) -> String {
+    if value.chars().all(is_bare_char) {
+        return value.to_string();
+    }
+
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '\\' => quoted.push_str("\\\\"),
+            '"' => quoted.push_str("\\\""),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+    column: usize,
+}
+
+impl Parser {
+    fn new(input: &str) -> Parser {
+        Parser {
+            chars: input.chars().collect(),
+            pos: 0,
+            line: 1,
+            column: 1,
+        }
+    }
+
+    fn parse(mut self) -> Result<EnvFile, ParseError> {
+        let mut lines = Vec::new();
+        let mut trailing_newline = false;
+
+        while self.peek().is_some() {
+            let start = self.pos;
+            let line = self.parse_line()?;
+            let raw: String = self.chars[start..self.pos].iter().collect();
+
+            lines.push(match line {
+                Line::Blank(_) => Line::Blank(raw),
+                Line::Comment(_) => Line::Comment(raw),
+                Line::Variable(v) => Line::Variable(Variable {
+                    raw: Some(raw),
+                    ..v
+                }),
+            });
+
+            trailing_newline = self.bump() == Some('\n') && self.peek().is_none();
+        }
+
+        Ok(EnvFile {
+            lines,
+            trailing_newline,
+        })
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn error(&self, message: &str) -> ParseError {
+        ParseError {
+            line: self.line,
+            column: self.column,
+            message: message.to_string(),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(' ') | Some('\t') | Some('\r') = self.peek() {
+            self.bump();
+        }
+    }
+
+    fn skip_to_end_of_line(&mut self) {
+        while !matches!(self.peek(), None | Some('\n')) {
+            self.bump();
+        }
+    }
+
+    fn at_end_of_line(&self) -> bool {
+        matches!(self.peek(), None | Some('\n'))
+    }
+
+    fn starts_with_export(&self) -> bool {
+        let prefix: Vec<char> = "export".chars().collect();
+        self.chars[self.pos..].starts_with(&prefix)
+            && matches!(
+                self.chars.get(self.pos + prefix.len()),
+                Some(' ') | Some('\t')
+            )
+    }
+
+    fn parse_line(&mut self) -> Result<Line, ParseError> {
+        self.skip_whitespace();
+
+        if self.at_end_of_line() {
+            return Ok(Line::Blank(String::new()));
+        }
+
+        if self.peek() == Some('#') {
+            self.skip_to_end_of_line();
+            return Ok(Line::Comment(String::new()));
+        }
+
+        let export = self.starts_with_export();
+        if export {
+            for _ in 0.."export".len() {
+                self.bump();
+            }
+            self.skip_whitespace();
+        }
+
+        let key = self.parse_key()?;
+        self.skip_whitespace();
+        if self.peek() != Some('=') {
+            return Err(self.error(&format!("expected '=' after `{}`", key)));
+        }
+        self.bump();
+        self.skip_whitespace();
+
+        let literal = self.peek() == Some('\'');
+        let value = match self.peek() {
+            Some('\'') => self.parse_single_quoted()?,
+            Some('"') => self.parse_double_quoted()?,
+            _ => return Ok(Line::Variable(self.parse_unquoted(key, export))),
+        };
+
+        let comment_start = self.pos;
+        self.skip_whitespace();
+        let mut comment = None;
+        if self.peek() == Some('#') {
+            self.skip_to_end_of_line();
+            comment = Some(self.chars[comment_start..self.pos].iter().collect());
+        }
+        if !self.at_end_of_line() {
+            return Err(self.error("unexpected character after the closing quote"));
+        }
+
+        Ok(Line::Variable(Variable {
+            key,
+            value,
+            export,
+            comment,
+            literal,
+            raw: None,
+        }))
+    }
+
+    fn parse_key(&mut self) -> Result<String, ParseError> {
+        match self.peek() {
+            Some(c) if c.is_ascii_alphabetic() || c == '_' => (),
+            _ => return Err(self.error("expected a variable name")),
+        }
+
+        let mut key = String::new();
+        while let Some(c) = self.peek() {
+            if !(c.is_ascii_alphanumeric() || c == '_' || c == '.') {
+                break;
+            }
+            key.push(c);
+            self.bump();
+        }
+        Ok(key)
+    }
+
+    fn parse_unquoted(&mut self, key: String, export: bool) -> Variable {
+        let mut value = String::new();
+        let mut previous = '=';
+        while let Some(c) = self.peek() {
+            if c == '\n' || (c == '#' && (previous == ' ' || previous == '\t')) {
+                break;
+            }
+            value.push(c);
+            previous = c;
+            self.bump();
+        }
+        let trimmed = value.trim_end().len();
+        let comment = match self.peek() {
+            Some('#') => {
+                let start = self.pos;
+                self.skip_to_end_of_line();
+                let rest: String = self.chars[start..self.pos].iter().collect();
+                Some(format!("{}{}", &value[trimmed..], rest))
+            }
+            _ => None,
+        };
+        value.truncate(trimmed);
+
+        Variable {
+            key,
+            value,
+            export,
+            comment,
+            literal: false,
+            raw: None,
+        }
+    }
+
+    fn parse_single_quoted(&mut self) -> Result<String, ParseError> {
+        let opening = self.error("unterminated single-quoted value");
+        self.bump();
+
+        let mut value = String::new();
+        loop {
+            match self.bump() {
+                Some('\'') => return Ok(value),
+                Some(c) => value.push(c),
+                None => return Err(opening),
+            }
+        }
+    }
+
+    fn parse_double_quoted(&mut self) -> Result<String, ParseError> {
+        let opening = self.error("unterminated double-quoted value");
+        self.bump();
+
+        let mut value = String::new();
+        loop {
+            match self.bump() {
+                Some('"') => return Ok(value),
+                Some('\\') => match self.bump() {
+                    Some('n') => value.push('\n'),
+                    Some('r') => value.push('\r'),
+                    Some('t') => value.push('\t'),
+                    Some(c @ '\\') | Some(c @ '"') | Some(c @ '\'') => value.push(c),
+                    Some(c) => {
+                        value.push('\\');
+                        value.push(c);
+                    }
+                    None => return Err(opening),
+                },
+                Some(c) => value.push(c),
+                None => return Err(opening),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_values() {
+        let env = EnvFile::parse(
+            "# comment\n\
+             PLAIN=value\n\
+             export EXPORTED=1\n\
+             SPACED = spaced value  # trailing comment\n\
+             SINGLE='$raw \\n'\n\
+             DOUBLE=\"line\\nbreak \\\"quoted\\\"\"\n\
+             MULTI=\"first\n\
+             second\"\n\
+             EMPTY=\n\
+             HASH=a#b\n",
+        )
+        .unwrap();
+
+        assert_eq!(env.get("PLAIN"), Some("value"));
+        assert_eq!(env.get("EXPORTED"), Some("1"));
+        assert!(env.variables().any(|v| v.key == "EXPORTED" && v.export));
+        assert_eq!(env.get("SPACED"), Some("spaced value"));
+        assert_eq!(env.get("SINGLE"), Some("$raw \\n"));
+        assert_eq!(env.get("DOUBLE"), Some("line\nbreak \"quoted\""));
+        assert_eq!(env.get("MULTI"), Some("first\nsecond"));
+        assert_eq!(env.get("EMPTY"), Some(""));
+        assert_eq!(env.get("HASH"), Some("a#b"));
+        assert_eq!(env.get("MISSING"), None);
+    }
+
+    #[test]
+    fn test_parse_single_quoted_is_literal() {
+        let env = EnvFile::parse("KEY='a \\n ${B}'").unwrap();
+        assert_eq!(env.get("KEY"), Some("a \\n ${B}"));
+    }
+
+    #[test]
+    fn test_round_trip_is_lossless() {
+        let inputs = [
+            "",
+            "\n",
+            "A=1",
+            "A=1\n\n# comment\n  export   B = \"x\\ty\"   # note\nC='multi\nline'\r\n",
+        ];
+        for input in inputs.iter() {
+            let env = EnvFile::parse(input).unwrap();
+            assert_eq!(&env.to_string(), input);
+        }
+    }
+
+    #[test]
+    fn test_set_and_unset_preserve_other_lines() {
+        let mut env = EnvFile::parse("# header\nA=1 # keep\nB=2\nD='x'  # quoted\n").unwrap();
+        env.set("B", "two words");
+        env.set("C", "3");
+        env.set("D", "y");
+        assert!(env.unset("A"));
+        assert!(!env.unset("A"));
+        assert_eq!(
+            env.to_string(),
+            "# header\nB=\"two words\"\nD=y  # quoted\nC=3\n"
+        );
+
+        let mut commented = EnvFile::parse("A=1 # keep").unwrap();
+        commented.set("A", "2");
+        assert_eq!(commented.to_string(), "A=2 # keep");
+
+        let reparsed = EnvFile::parse(&env.to_string()).unwrap();
+        assert_eq!(reparsed.get("B"), Some("two words"));
+    }
+
+    #[test]
+    fn test_layer() {
+        let mut env = EnvFile::parse("# base\nA=1\nB=2\n").unwrap();
+        env.layer(&EnvFile::parse("# child\nB=3 # override\nC=4\n").unwrap());
+        assert_eq!(
+            env.to_string(),
+            "# base\nA=1\n# child\nB=3 # override\nC=4\n"
+        );
+        assert_eq!(env.get("B"), Some("3"));
+    }
+
+    #[test]
+    fn test_is_valid_key() {
+        assert!(is_valid_key("DATABASE_URL"));
+        assert!(is_valid_key("_a.b1"));
+        assert!(!is_valid_key(""));
+        assert!(!is_valid_key("1B"));
+        assert!(!is_valid_key("A-B"));
+    }
+
+    #[test]
+    fn test_parse_errors_have_position() {
+        let err = EnvFile::parse("A=1\n1B=2").unwrap_err();
+        assert_eq!((err.line, err.column), (2, 1));
+
+        let err = EnvFile::parse("A=1\nKEY value").unwrap_err();
+        assert_eq!((err.line, err.column), (2, 5));
+
+        let err = EnvFile::parse("A=\"open\nB=2").unwrap_err();
+        assert_eq!((err.line, err.column), (1, 3));
+
+        let err = EnvFile::parse("A='x' y").unwrap_err();
+        assert_eq!((err.line, err.column), (1, 7));
+    }
+}
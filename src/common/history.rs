@@ -0,0 +1,246 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::result::Result;
+use std::time::SystemTime;
+
+use super::{decrypt_if_needed, utc_timestamp, write_atomic, Cipher, Entry, Integrity, Project};
+use crate::error::Error as CommandError;
+
+pub const HISTORY_DIRNAME: &str = "history";
+/// Number of revisions kept for each profile when the project does not set its own limit
+pub const DEFAULT_HISTORY_LIMIT: usize = 10;
+
+/// A previous content of a profile, kept when the profile was changed
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct Revision {
+    /// Numbers increase with every change of the profile and are never reused
+    pub number: u32,
+    /// When the profile was changed, as `utc_timestamp`
+    pub timestamp: String,
+    /// Hash of the stored file of the revision. See `Project::hash_env`
+    pub hash: String,
+    /// Message given with the change, or an empty string
+    pub message: String,
+}
+
+/// Directory which holds the stored files of the revisions of a profile, named by number
+#[must_use]
+pub fn history_dir(project_dir: &Path, project_id: &str, entry_id: &str) -> PathBuf {
+    project_dir
+        .join(project_id)
+        .join(HISTORY_DIRNAME)
+        .join(entry_id)
+}
+
+/// Stored file of the revision `number` of a profile
+#[must_use]
+pub fn revision_path(project_dir: &Path, project_id: &str, entry: &Entry, number: u32) -> PathBuf {
+    history_dir(project_dir, project_id, &entry.id).join(number.to_string())
+}
+
+/// Keeps the stored content of the profile `name` as a new revision before it is changed,
+/// and drops the oldest revisions beyond the history limit of the project. The stored
+/// file is copied as it is, so the revisions of an encrypted project stay encrypted.
+/// The config has to be saved by the caller.
+///
+/// # Errors
+///
+/// Will return `Err` if the profile does not exist or it fails to write the revision
+pub fn record_revision(
+    project_dir: &Path,
+    project_id: &str,
+    project: &mut Project,
+    name: &str,
+    message: &str,
+) -> Result<(), Error> {
+    let index = project
+        .entries
+        .iter()
+        .position(|entry| entry.name == name)
+        .ok_or_else(|| Error::new(ErrorKind::NotFound, "the profile does not exist"))?;
+    if project.history_limit() > 0 {
+        let entry = &project.entries[index];
+        let stored = match fs::read(project_dir.join(project_id).join(&entry.id)) {
+            Ok(v) => v,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        let number = entry.history.last().map_or(1, |v| v.number + 1);
+        fs::create_dir_all(history_dir(project_dir, project_id, &entry.id))?;
+        write_atomic(
+            &revision_path(project_dir, project_id, entry, number),
+            &stored,
+        )?;
+
+        let revision = Revision {
+            number,
+            timestamp: utc_timestamp(SystemTime::now()),
+            hash: project.hash_env(&stored),
+            message: message.to_string(),
+        };
+        project.entries[index].history.push(revision);
+    }
+    prune_history(project_dir, project_id, project)
+}
+
+/// Drops the oldest revisions of every profile beyond the history limit of the project.
+/// The config has to be saved by the caller.
+///
+/// # Errors
+///
+/// Will return `Err` if it fails to delete the stored file of a revision
+pub fn prune_history(
+    project_dir: &Path,
+    project_id: &str,
+    project: &mut Project,
+) -> Result<(), Error> {
+    let limit = project.history_limit();
+    for entry in &mut project.entries {
+        let excess = entry.history.len().saturating_sub(limit);
+        for revision in entry.history.drain(..excess) {
+            let path =
+                history_dir(project_dir, project_id, &entry.id).join(revision.number.to_string());
+            match fs::remove_file(path) {
+                Err(e) if e.kind() != ErrorKind::NotFound => return Err(e),
+                _ => (),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reads a revision of a profile after checking it against its recorded hash
+///
+/// # Errors
+///
+/// Will return `Err` if the profile has no such revision, its stored file does not match
+/// its hash, or it can not be decrypted
+pub fn read_revision(
+    project_dir: &Path,
+    project_id: &str,
+    project: &Project,
+    entry: &Entry,
+    number: u32,
+    cipher: Option<&Cipher>,
+) -> Result<String, CommandError> {
+    let revision = entry
+        .history
+        .iter()
+        .find(|v| v.number == number)
+        .ok_or_else(|| CommandError::NoRevision(entry.name.clone(), number))?;
+    let stored = fs::read(revision_path(project_dir, project_id, entry, number))
+        .map_err(|_| CommandError::NoRevision(entry.name.clone(), number))?;
+    if project.hash_env(&stored) != revision.hash {
+        return Err(CommandError::Tampered(format!(
+            "{} revision {}",
+            entry.name, number
+        )));
+    }
+
+    let env = decrypt_if_needed(stored, cipher).map_err(CommandError::Encryption)?;
+    Ok(String::from_utf8_lossy(&env).into_owned())
+}
+
+/// # Errors
+///
+/// Will return `Err` if the stored file of the revision exists but can not be read
+pub fn check_revision(
+    project_dir: &Path,
+    project_id: &str,
+    project: &Project,
+    entry: &Entry,
+    revision: &Revision,
+) -> Result<Integrity, Error> {
+    let path = revision_path(project_dir, project_id, entry, revision.number);
+    match fs::read(path) {
+        Ok(v) if project.hash_env(&v) == revision.hash => Ok(Integrity::Intact),
+        Ok(_) => Ok(Integrity::Modified),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(Integrity::Missing),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::common::{create_cipher, is_encrypted, write_profile};
+    use tempdir::TempDir;
+
+    fn project_with(project_dir: &Path, env: &str, cipher: Option<&Cipher>) -> Project {
+        fs::create_dir_all(project_dir.join("project")).unwrap();
+        let mut project = Project {
+            entries: vec![Entry {
+                name: "prod".to_string(),
+                id: "prod id".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        write_profile(project_dir, "project", &mut project, "prod", env, cipher).unwrap();
+        project
+    }
+
+    #[test]
+    fn test_record_and_read_revision() {
+        let tmp_dir = TempDir::new("test_record_and_read_revision").unwrap();
+        let tmp_dir_path = tmp_dir.path().to_owned();
+        fs::create_dir_all(tmp_dir_path.join("project")).unwrap();
+        let cipher = create_cipher(&tmp_dir_path, "project", "secret").unwrap();
+        let mut project = project_with(&tmp_dir_path, "A=1", Some(&cipher));
+
+        record_revision(&tmp_dir_path, "project", &mut project, "prod", "rotate").unwrap();
+        write_profile(
+            &tmp_dir_path,
+            "project",
+            &mut project,
+            "prod",
+            "A=2",
+            Some(&cipher),
+        )
+        .unwrap();
+
+        let entry = &project.entries[0];
+        assert_eq!(entry.history.len(), 1);
+        assert_eq!(entry.history[0].message, "rotate");
+        let path = history_dir(&tmp_dir_path, "project", "prod id").join("1");
+        assert!(is_encrypted(&fs::read(&path).unwrap()));
+        let env = read_revision(&tmp_dir_path, "project", &project, entry, 1, Some(&cipher));
+        assert_eq!(env.unwrap(), "A=1");
+
+        let missing = read_revision(&tmp_dir_path, "project", &project, entry, 2, Some(&cipher));
+        assert!(matches!(missing, Err(CommandError::NoRevision(_, 2))));
+        fs::write(&path, "A=3").unwrap();
+        let tampered = read_revision(&tmp_dir_path, "project", &project, entry, 1, Some(&cipher));
+        assert!(matches!(tampered, Err(CommandError::Tampered(_))));
+    }
+
+    #[test]
+    fn test_history_limit() {
+        let tmp_dir = TempDir::new("test_history_limit").unwrap();
+        let tmp_dir_path = tmp_dir.path().to_owned();
+        let mut project = project_with(&tmp_dir_path, "A=0", None);
+        project.history_limit = Some(2);
+
+        for i in 1..=3 {
+            record_revision(&tmp_dir_path, "project", &mut project, "prod", "").unwrap();
+            let env = format!("A={}", i);
+            write_profile(&tmp_dir_path, "project", &mut project, "prod", &env, None).unwrap();
+        }
+        let numbers = project.entries[0]
+            .history
+            .iter()
+            .map(|v| v.number)
+            .collect::<Vec<u32>>();
+        assert_eq!(numbers, vec![2, 3]);
+        let dir = history_dir(&tmp_dir_path, "project", "prod id");
+        assert!(!dir.join("1").exists());
+
+        project.history_limit = Some(0);
+        record_revision(&tmp_dir_path, "project", &mut project, "prod", "").unwrap();
+        assert!(project.entries[0].history.is_empty());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,244 @@
+use std::path::Path;
+use std::result::Result;
+
+use super::{read_env, render, Cipher, EnvFile, Interpolation, Project};
+use crate::error::Error as CommandError;
+
+/// A variable of a resolved profile with the name of the profile it comes from
+#[derive(Debug, PartialEq)]
+pub struct ResolvedVariable {
+    pub key: String,
+    pub value: String,
+    pub source: String,
+}
+
+/// Returns the profiles which make up the profile `id`, from the lowest priority to the
+/// highest: its bases in their order, each after its own bases, and the profile itself last.
+/// A profile which is reached through several bases is only included the first time.
+///
+/// # Errors
+///
+/// Will return `Err` if a base does not exist or the bases form a cycle
+pub fn linearize(project: &Project, id: &str) -> Result<Vec<String>, CommandError> {
+    let mut order = Vec::new();
+    visit(project, id, &mut Vec::new(), &mut order)?;
+    Ok(order)
+}
+
+fn visit(
+    project: &Project,
+    id: &str,
+    stack: &mut Vec<String>,
+    order: &mut Vec<String>,
+) -> Result<(), CommandError> {
+    let name = |id: &str| {
+        project
+            .entries
+            .iter()
+            .find(|entry| entry.id == id)
+            .map_or_else(|| id.to_string(), |entry| entry.name.clone())
+    };
+    if stack.iter().any(|v| v == id) {
+        let mut cycle = stack
+            .iter()
+            .skip_while(|v| *v != id)
+            .map(|v| name(v))
+            .collect::<Vec<String>>();
+        cycle.push(name(id));
+        return Err(CommandError::InheritanceCycle(cycle.join(" -> ")));
+    }
+    if order.iter().any(|v| v == id) {
+        return Ok(());
+    }
+
+    let entry = project
+        .entries
+        .iter()
+        .find(|entry| entry.id == id)
+        .ok_or(CommandError::NotExists)?;
+    stack.push(id.to_string());
+    for base in &entry.bases {
+        visit(project, base, stack, order)?;
+    }
+    stack.pop();
+    order.push(id.to_string());
+    Ok(())
+}
+
+/// Reads every profile which makes up the profile `id`, in the order of `linearize`
+///
+/// # Errors
+///
+/// Will return `Err` if the bases are not valid, or if any of the profiles can not be read or parsed
+pub fn read_layers(
+    project_dir: &Path,
+    project_id: &str,
+    project: &Project,
+    id: &str,
+    cipher: Option<&Cipher>,
+) -> Result<Vec<(String, EnvFile)>, CommandError> {
+    let mut layers = Vec::new();
+    for layer_id in linearize(project, id)? {
+        let entry = project
+            .entries
+            .iter()
+            .find(|entry| entry.id == layer_id)
+            .ok_or(CommandError::NotExists)?;
+        let (_, env) = read_env(project_dir, project_id, project, entry, cipher)?;
+        let env = EnvFile::parse(&env.unwrap_or_default())?;
+        layers.push((entry.name.clone(), env));
+    }
+    Ok(layers)
+}
+
+/// Merges the layers of a profile into a single env file. Comments are kept, and each key
+/// is only assigned once with the value of the layer with the highest priority.
+#[must_use]
+pub fn merge_layers(layers: &[(String, EnvFile)]) -> EnvFile {
+    let mut merged = EnvFile::default();
+    for (_, env) in layers {
+        merged.layer(env);
+    }
+    merged
+}
+
+/// Reads the profile `id` with its bases merged in
+///
+/// # Errors
+///
+/// Will return `Err` if the bases are not valid, or if any of the profiles can not be read or parsed
+pub fn resolve(
+    project_dir: &Path,
+    project_id: &str,
+    project: &Project,
+    id: &str,
+    cipher: Option<&Cipher>,
+) -> Result<EnvFile, CommandError> {
+    let layers = read_layers(project_dir, project_id, project, id, cipher)?;
+    Ok(merge_layers(&layers))
+}
+
+/// Lists the variables of the merged profile with the profile each of them comes from
+#[must_use]
+pub fn annotate_layers(layers: &[(String, EnvFile)]) -> Vec<ResolvedVariable> {
+    let merged = merge_layers(layers);
+    merged
+        .variables()
+        .map(|v| ResolvedVariable {
+            key: v.key.clone(),
+            value: v.value.clone(),
+            source: layers
+                .iter()
+                .rev()
+                .find(|(_, env)| env.get(&v.key).is_some())
+                .map(|(name, _)| name.clone())
+                .unwrap_or_default(),
+        })
+        .collect()
+}
+
+/// Returns the content of the profile `id` with its bases merged in and its references
+/// interpolated, as it is written to `.env`. The content of a profile without bases is
+/// returned as it is stored when interpolation is off.
+///
+/// # Errors
+///
+/// Will return `Err` if any of the profiles can not be read, or if the bases or the
+/// references are not valid
+pub fn materialize(
+    project_dir: &Path,
+    project_id: &str,
+    project: &Project,
+    id: &str,
+    cipher: Option<&Cipher>,
+) -> Result<String, CommandError> {
+    let entry = project
+        .entries
+        .iter()
+        .find(|entry| entry.id == id)
+        .ok_or(CommandError::NotExists)?;
+    if entry.bases.is_empty() && project.interpolation == Interpolation::Off {
+        let (_, env) = read_env(project_dir, project_id, project, entry, cipher)?;
+        return Ok(env.unwrap_or_default());
+    }
+
+    let env = resolve(project_dir, project_id, project, id, cipher)?;
+    Ok(render(&env, project.interpolation)?.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{write_profile, Entry};
+    use super::*;
+    use std::fs;
+    use tempdir::TempDir;
+
+    fn entry(name: &str, bases: &[&str]) -> Entry {
+        Entry {
+            name: name.to_string(),
+            id: format!("{} id", name),
+            bases: bases.iter().map(|v| format!("{} id", v)).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_linearize() {
+        let project = Project {
+            entries: vec![
+                entry("common", &[]),
+                entry("web", &["common"]),
+                entry("worker", &["common"]),
+                entry("dev", &["web", "worker"]),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(
+            linearize(&project, "dev id").unwrap(),
+            vec!["common id", "web id", "worker id", "dev id"]
+        );
+    }
+
+    #[test]
+    fn test_linearize_detects_cycles() {
+        let project = Project {
+            entries: vec![entry("a", &["b"]), entry("b", &["c"]), entry("c", &["a"])],
+            ..Default::default()
+        };
+        match linearize(&project, "a id") {
+            Err(CommandError::InheritanceCycle(v)) => assert_eq!(v, "a -> b -> c -> a"),
+            v => panic!("unexpected result {:?}", v),
+        }
+    }
+
+    #[test]
+    fn test_materialize_and_annotate() {
+        let tmp_dir = TempDir::new("test_materialize").unwrap();
+        let tmp_dir_path = tmp_dir.path().to_owned();
+        fs::create_dir(tmp_dir_path.join("project")).unwrap();
+        let mut project = Project {
+            entries: vec![entry("common", &[]), entry("dev", &["common"])],
+            ..Default::default()
+        };
+        let envs = [("common", "A=1\nB=2\n"), ("dev", "B=3\n")];
+        for (name, env) in &envs {
+            write_profile(&tmp_dir_path, "project", &mut project, name, env, None).unwrap();
+        }
+
+        let env = materialize(&tmp_dir_path, "project", &project, "dev id", None).unwrap();
+        assert_eq!(env, "A=1\nB=3\n");
+
+        let layers = read_layers(&tmp_dir_path, "project", &project, "dev id", None).unwrap();
+        let sources = annotate_layers(&layers)
+            .into_iter()
+            .map(|v| (v.key, v.source))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sources,
+            vec![
+                ("A".to_string(), "common".to_string()),
+                ("B".to_string(), "dev".to_string())
+            ]
+        );
+    }
+}
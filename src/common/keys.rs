@@ -0,0 +1,82 @@
+use directories::BaseDirs;
+use std::env;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::os::unix::fs::DirBuilderExt;
+use std::path::{Path, PathBuf};
+use std::result::Result;
+
+use super::write_private_file;
+
+/// Directory which holds the HMAC keys of the projects instead of the default one
+pub const KEY_DIR_ENV: &str = "RPILOT_KEY_DIR";
+
+/// Returns the directory of the HMAC keys of the projects. It is kept out of the data dir,
+/// so that whoever can only write to the data dir can not recompute the hashes of the
+/// profiles. It is `RPILOT_KEY_DIR` when it is set, and `rpilot/keys` in the config dir of
+/// the platform otherwise.
+///
+/// # Errors
+///
+/// Will return `Err` if it fails to retrieve the config dir
+pub fn get_key_dir() -> Result<PathBuf, Error> {
+    if let Some(dir) = env::var_os(KEY_DIR_ENV).filter(|v| !v.is_empty()) {
+        return Ok(PathBuf::from(dir));
+    }
+    match BaseDirs::new() {
+        Some(v) => Ok(v.config_dir().join("rpilot").join("keys")),
+        None => Err(Error::other("Failed at get_key_dir()")),
+    }
+}
+
+fn key_path(key_dir: &Path, project_id: &str) -> PathBuf {
+    key_dir.join(format!("{}.key", project_id))
+}
+
+/// Reads the base64 HMAC key of a project, or `None` when the project has none
+///
+/// # Errors
+///
+/// Will return `Err` if the key file exists but can not be read
+pub fn read_hmac_key(key_dir: &Path, project_id: &str) -> Result<Option<String>, Error> {
+    match fs::read_to_string(key_path(key_dir, project_id)) {
+        Ok(v) => Ok(Some(v.trim().to_string())),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Stores the base64 HMAC key of a project in a file which only the current user can read
+///
+/// # Errors
+///
+/// Will return `Err` if it fails to create the key dir or to write the key file
+pub fn write_hmac_key(key_dir: &Path, project_id: &str, key: &str) -> Result<(), Error> {
+    fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(key_dir)?;
+    write_private_file(&key_path(key_dir, project_id), key)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_write_and_read_hmac_key() {
+        let tmp_dir = TempDir::new("test_write_and_read_hmac_key").unwrap();
+        let key_dir = tmp_dir.path().join("keys");
+        assert_eq!(read_hmac_key(&key_dir, "project").unwrap(), None);
+
+        write_hmac_key(&key_dir, "project", "c2VjcmV0").unwrap();
+        assert_eq!(
+            read_hmac_key(&key_dir, "project").unwrap(),
+            Some("c2VjcmV0".to_string())
+        );
+        let metadata = fs::metadata(key_path(&key_dir, "project")).unwrap();
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+    }
+}
@@ -0,0 +1,182 @@
+use fs2::FileExt;
+use log::debug;
+use std::env;
+use std::fs;
+use std::io::{Error, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::result::Result;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::error::Error as CommandError;
+
+/// File in the data dir of a project which is locked while a command changes the project.
+/// It holds the pid of the process which locked it last.
+pub const LOCK_FILENAME: &str = "lock";
+/// Seconds to wait for another rpilot to release a project
+pub const LOCK_TIMEOUT_ENV: &str = "RPILOT_LOCK_TIMEOUT";
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+const RETRY_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Lock files which this process holds, so that taking the lock of a project again while
+/// it is held does not wait for itself
+static HELD: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// Advisory lock on the data dir of a project. It is released when it is dropped, or
+/// by the system when rpilot exits.
+pub struct ProjectLock {
+    /// `None` when the lock was already held by this process
+    file: Option<(fs::File, PathBuf)>,
+}
+
+impl Drop for ProjectLock {
+    fn drop(&mut self) {
+        if let Some((_, path)) = &self.file {
+            if let Ok(mut held) = HELD.lock() {
+                held.retain(|v| v != path);
+            }
+        }
+    }
+}
+
+/// Locks the project for the read-modify-write of its config and profiles. Commands which
+/// change the project take it before they read the config and hold it until they return.
+/// A change of the passphrase which was interrupted is finished once the lock is taken.
+/// Taking it again while this process holds it returns at once.
+///
+/// # Errors
+///
+/// Will return `Err` if another process holds the lock for longer than the timeout, or the
+/// lock file can not be opened
+pub fn lock_project(project_dir: &Path, id: &str) -> Result<ProjectLock, CommandError> {
+    let lock = lock_held_once(&project_dir.join(id).join(LOCK_FILENAME))?;
+    super::finish_replace(&project_dir.join(id).join(super::JOURNAL_FILENAME))?;
+    Ok(lock)
+}
+
+/// Locks the files at the root of the data dir which every project shares, like
+/// `lock_project` does for the files of a project
+///
+/// # Errors
+///
+/// Will return `Err` if another process holds the lock for longer than the timeout, or the
+/// lock file can not be opened
+pub fn lock_data_dir(project_dir: &Path) -> Result<ProjectLock, CommandError> {
+    lock_held_once(&project_dir.join(LOCK_FILENAME))
+}
+
+fn lock_held_once(path: &Path) -> Result<ProjectLock, CommandError> {
+    if HELD
+        .lock()
+        .map(|v| v.iter().any(|v| v == path))
+        .unwrap_or(false)
+    {
+        return Ok(ProjectLock { file: None });
+    }
+    let timeout = env::var(LOCK_TIMEOUT_ENV)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_TIMEOUT);
+    let lock = lock_file(path, timeout)?;
+    if let Ok(mut held) = HELD.lock() {
+        held.push(path.to_path_buf());
+    }
+    Ok(lock)
+}
+
+fn lock_file(path: &Path, timeout: Duration) -> Result<ProjectLock, CommandError> {
+    let open_error = |e: Error| {
+        Error::new(
+            e.kind(),
+            format!("{} can not be locked ({})", path.display(), e),
+        )
+    };
+    let mut file = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        // The pid of the holder must stay readable until the lock is taken
+        .truncate(false)
+        .open(path)
+        .map_err(open_error)?;
+
+    let start = Instant::now();
+    loop {
+        match file.try_lock_exclusive() {
+            Ok(()) => break,
+            Err(e) if e.raw_os_error() == fs2::lock_contended_error().raw_os_error() => {
+                if start.elapsed() >= timeout {
+                    return Err(CommandError::Locked(holder(&mut file)));
+                }
+                debug!("Waiting for the lock of {}", path.display());
+                thread::sleep(RETRY_INTERVAL);
+            }
+            Err(e) => return Err(open_error(e).into()),
+        }
+    }
+
+    file.set_len(0)?;
+    file.seek(SeekFrom::Start(0))?;
+    file.write_all(process::id().to_string().as_bytes())?;
+    Ok(ProjectLock {
+        file: Some((file, path.to_path_buf())),
+    })
+}
+
+/// Describes the process which holds the lock, from the pid it wrote
+fn holder(file: &mut fs::File) -> String {
+    let mut content = String::new();
+    let pid = file
+        .seek(SeekFrom::Start(0))
+        .and_then(|_| file.read_to_string(&mut content))
+        .ok()
+        .and_then(|_| content.trim().parse::<u32>().ok());
+    match pid {
+        Some(v) => format!("pid {}", v),
+        None => "another process".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_lock_file() {
+        let tmp_dir = TempDir::new("test_lock_file").unwrap();
+        let path = tmp_dir.path().join(LOCK_FILENAME);
+        let lock = lock_file(&path, Duration::from_secs(0)).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            process::id().to_string()
+        );
+
+        // flock locks conflict between open files even in the same process
+        match lock_file(&path, Duration::from_millis(100)) {
+            Err(CommandError::Locked(v)) => assert_eq!(v, format!("pid {}", process::id())),
+            _ => panic!("the lock was taken twice"),
+        }
+
+        drop(lock);
+        assert!(lock_file(&path, Duration::from_secs(0)).is_ok());
+    }
+
+    #[test]
+    fn test_lock_project_again() {
+        let tmp_dir = TempDir::new("test_lock_project_again").unwrap();
+        let tmp_dir_path = tmp_dir.path().to_owned();
+        let path = tmp_dir_path.join(LOCK_FILENAME);
+        let lock = lock_project(&tmp_dir_path, "").unwrap();
+        drop(lock_project(&tmp_dir_path, "").unwrap());
+        assert!(lock_file(&path, Duration::from_secs(0)).is_err());
+
+        drop(lock);
+        drop(lock_project(&tmp_dir_path, "").unwrap());
+        assert!(lock_file(&path, Duration::from_secs(0)).is_ok());
+    }
+}
@@ -0,0 +1,1019 @@
+// savefile-derive generates its impls inside a function-local const block
+#![allow(non_local_definitions)]
+
+use std::env;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs;
+use std::io::{stdin, stdout, Error, IsTerminal, Write};
+use std::os::unix::fs::MetadataExt;
+use std::result::Result;
+use std::str::FromStr;
+
+use directories::{BaseDirs, ProjectDirs};
+use log::{debug, info};
+use ring::digest::{digest, SHA256};
+use ring::hmac;
+use ring::rand::{SecureRandom, SystemRandom};
+use savefile_derive::Savefile;
+use serde::{Deserialize, Serialize};
+use std::path::{Component, Path, PathBuf};
+
+use crate::error::Error as CommandError;
+
+mod atomic;
+mod backup;
+mod config;
+mod crypto;
+mod env_file;
+mod history;
+mod inherit;
+mod interpolate;
+mod keys;
+mod lock;
+mod schema;
+pub use atomic::{finish_replace, replace_all, replace_with, write_atomic, write_private_file};
+pub use backup::{backup_env, list_backups, read_backup, utc_timestamp, BACKUPS_DIRNAME};
+pub use config::{CONFIG_VERSION, LEGACY_CONFIG_FILENAME, LEGACY_CONFIG_VERSION};
+pub use crypto::{
+    create_cipher, is_encrypted, is_project_encrypted, load_cipher, new_cipher,
+    read_new_passphrase, Cipher, ENCRYPTION_FILENAME, NEW_PASSPHRASE_ENV, PASSPHRASE_ENV,
+};
+pub use env_file::{is_valid_key, EnvFile, Line, ParseError, Variable};
+pub use history::{
+    check_revision, history_dir, prune_history, read_revision, record_revision, revision_path,
+    Revision, DEFAULT_HISTORY_LIMIT, HISTORY_DIRNAME,
+};
+pub use inherit::{
+    annotate_layers, linearize, materialize, merge_layers, read_layers, resolve, ResolvedVariable,
+};
+pub use interpolate::{interpolate, render, InterpolationError};
+pub use keys::{get_key_dir, read_hmac_key, write_hmac_key, KEY_DIR_ENV};
+pub use lock::{lock_data_dir, lock_project, ProjectLock, LOCK_FILENAME, LOCK_TIMEOUT_ENV};
+pub use schema::{Issue, Schema, EXAMPLE_FILENAME, SCHEMA_FILENAME};
+
+pub const ID_FILENAME: &str = ".rpilot";
+pub const CONFIG_FILENAME: &str = "config";
+/// Data dir to use instead of the default one of the platform
+pub const HOME_ENV: &str = "RPILOT_HOME";
+/// Directories, separated by `:`, above which `.rpilot` is not searched for. Defaults to the
+/// home directory, and an empty value searches up to the root.
+pub const CEILING_DIRS_ENV: &str = "RPILOT_CEILING_DIRECTORIES";
+/// File in the data dir of a project which records where the project is checked out
+pub const CHECKOUT_FILENAME: &str = "checkout";
+/// File in the data dir of a project which lists the files of an unfinished `replace_all`
+pub const JOURNAL_FILENAME: &str = "journal";
+/// How to recover from a config which can not be read
+const CONFIG_RECOVERY_HINT: &str =
+    "Please restore it from a backup of the data dir, or remove .rpilot and run init to start over.";
+
+/// How `apply` puts a profile at `.env`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ApplyMode {
+    /// A symlink to the stored profile
+    #[default]
+    Symlink,
+    /// A copy of the profile which only the current user can read
+    Copy,
+    /// A hard link to the stored profile
+    Hardlink,
+}
+
+impl fmt::Display for ApplyMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ApplyMode::Symlink => "symlink",
+            ApplyMode::Copy => "copy",
+            ApplyMode::Hardlink => "hardlink",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for ApplyMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "symlink" => Ok(ApplyMode::Symlink),
+            "copy" => Ok(ApplyMode::Copy),
+            "hardlink" => Ok(ApplyMode::Hardlink),
+            _ => Err(format!(
+                "{} is not a valid mode. Use symlink, copy or hardlink",
+                s
+            )),
+        }
+    }
+}
+
+/// Which references `apply` expands in the values of a profile
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Interpolation {
+    /// Profiles are written untouched
+    #[default]
+    Off,
+    /// `${VAR}` is expanded from the variables of the same profile
+    Profile,
+    /// Like `Profile`, and variables which the profile does not define are taken from the environment of rpilot
+    Env,
+}
+
+impl fmt::Display for Interpolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Interpolation::Off => "off",
+            Interpolation::Profile => "profile",
+            Interpolation::Env => "env",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Interpolation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(Interpolation::Off),
+            "profile" => Ok(Interpolation::Profile),
+            "env" => Ok(Interpolation::Env),
+            _ => Err(format!(
+                "{} is not a valid interpolation. Use off, profile or env",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Savefile, Serialize, Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct Entry {
+    pub name: String,
+    /// Hash of the stored file of the profile. See `Project::hash_env`
+    pub hash: String,
+    pub id: String,
+    /// Protected profiles always require typing their name to be applied
+    #[savefile_ignore]
+    pub protected: bool,
+    /// Ids of the profiles this profile inherits from, from the lowest priority to the highest
+    #[savefile_ignore]
+    pub bases: Vec<String>,
+    /// Previous contents of the profile, from the oldest to the newest
+    #[savefile_ignore]
+    #[savefile_introspect_ignore]
+    pub history: Vec<Revision>,
+}
+
+/// Config of a project. It is stored as JSON, see `config::encode`, and the savefile
+/// derive only remains to migrate the configs of the previous versions.
+#[derive(Savefile, Serialize, Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct Project {
+    pub entries: Vec<Entry>,
+    pub current_profile: Box<Option<String>>,
+    /// Apply profiles without asking for a confirmation
+    #[savefile_ignore]
+    pub auto_confirm: bool,
+    /// Mode used by `apply` when `--mode` is not given
+    #[savefile_ignore]
+    #[savefile_introspect_ignore]
+    pub apply_mode: ApplyMode,
+    /// Hash of the `.env` written by the last `apply` in copy mode, to detect local edits.
+    /// It is empty when the profile was linked instead.
+    #[savefile_ignore]
+    pub applied_hash: String,
+    /// Base64 key of the HMAC which replaces the plain SHA-256 of `Entry::hash` once it is set.
+    /// It is read from the key dir, see `get_key_dir`, and only the configs of the previous
+    /// versions stored it.
+    #[savefile_ignore]
+    #[serde(skip_serializing)]
+    pub hmac_key: String,
+    /// Whether `apply` expands `${VAR}` references when it writes a profile
+    #[savefile_ignore]
+    #[savefile_introspect_ignore]
+    pub interpolation: Interpolation,
+    /// Number of revisions kept for each profile. `DEFAULT_HISTORY_LIMIT` when it is not set.
+    #[savefile_ignore]
+    pub history_limit: Option<usize>,
+}
+
+/// Result of comparing the stored file of a profile with its recorded hash
+#[derive(Debug, PartialEq)]
+pub enum Integrity {
+    Intact,
+    Modified,
+    Missing,
+}
+
+impl Project {
+    #[must_use]
+    pub fn history_limit(&self) -> usize {
+        self.history_limit.unwrap_or(DEFAULT_HISTORY_LIMIT)
+    }
+
+    /// Hashes the stored, possibly encrypted, content of a profile: HMAC-SHA256 when the
+    /// project has an HMAC key, plain SHA-256 otherwise. The result is encoded in base64.
+    #[must_use]
+    pub fn hash_env(&self, stored: &[u8]) -> String {
+        match base64::decode(&self.hmac_key) {
+            Ok(key) if !key.is_empty() => {
+                let key = hmac::Key::new(hmac::HMAC_SHA256, &key);
+                base64::encode(hmac::sign(&key, stored).as_ref())
+            }
+            _ => hash_content(stored),
+        }
+    }
+
+    /// Generates a new HMAC key. The hashes of the entries have to be recomputed by the caller.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if it fails to generate the key
+    pub fn generate_hmac_key(&mut self) -> Result<(), Error> {
+        let mut key = [0; 32];
+        SystemRandom::new()
+            .fill(&mut key)
+            .map_err(|_| Error::other("Failed at generating a key"))?;
+        self.hmac_key = base64::encode(key);
+        Ok(())
+    }
+}
+
+/// The project rpilot is running on
+pub struct Workspace {
+    /// Directory which contains `.rpilot`, and where `.env` is applied. It is the current
+    /// directory or the nearest of its parents.
+    pub pwd: PathBuf,
+    /// Root of the data dir where the config and profiles of every project are stored
+    pub project_dir: PathBuf,
+    pub project_id: String,
+}
+
+/// # Errors
+///
+/// Will return `Err` if neither the current directory nor its parents are initialized for rpilot
+pub fn open_workspace() -> Result<Workspace, CommandError> {
+    let pwd = find_project_root(&env::current_dir()?).ok_or(CommandError::NotInitialized)?;
+    let project_dir = get_data_dir()?;
+    let project_id = get_project_id(&pwd).ok_or(CommandError::NotInitialized)?;
+    debug!("Retrieved project id is {}", project_id);
+    // Taking the lock finishes a change of the passphrase which was interrupted
+    if project_dir
+        .join(&project_id)
+        .join(JOURNAL_FILENAME)
+        .exists()
+    {
+        drop(lock_project(&project_dir, &project_id)?);
+    }
+    if let Err(e) = record_checkout(&project_dir, &project_id, &pwd) {
+        debug!("Failed at recording the checkout path: {}", e);
+    }
+
+    Ok(Workspace {
+        pwd,
+        project_dir,
+        project_id,
+    })
+}
+
+/// Returns the data dir which holds the config and profiles of every project. It is
+/// `RPILOT_HOME` when it is set, and the data dir of the platform otherwise.
+///
+/// # Errors
+///
+/// Will return `Err` if it fails to retrieve project dir path
+pub fn get_data_dir() -> Result<PathBuf, Error> {
+    data_dir_from(env::var_os(HOME_ENV))
+}
+
+fn data_dir_from(home: Option<OsString>) -> Result<PathBuf, Error> {
+    if let Some(home) = home.filter(|v| !v.is_empty()) {
+        return Ok(PathBuf::from(home));
+    }
+    match ProjectDirs::from("org", "rpilot", "rp") {
+        Some(proj_dirs) => Ok(PathBuf::from(proj_dirs.data_dir())),
+        None => Err(Error::other("Failed at get_data_dir()")),
+    }
+}
+
+/// Removes the `.` and `..` components of an absolute path without resolving symlinks, so
+/// that the same directory is always spelled the same way
+#[must_use]
+pub fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            _ => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+#[must_use]
+pub fn get_project_id(current_dir: &Path) -> Option<String> {
+    debug!("Reading .rpilot in the current directory");
+    let config = current_dir.join(ID_FILENAME);
+    fs::read_to_string(config).ok()
+}
+
+/// Returns the nearest directory from `dir` up which contains `.rpilot`. The search stops
+/// after the ceiling directories of `CEILING_DIRS_ENV`.
+#[must_use]
+pub fn find_project_root(dir: &Path) -> Option<PathBuf> {
+    let ceilings = match env::var_os(CEILING_DIRS_ENV) {
+        Some(v) => env::split_paths(&v)
+            .filter(|v| !v.as_os_str().is_empty())
+            .collect(),
+        None => BaseDirs::new()
+            .map(|v| vec![v.home_dir().to_path_buf()])
+            .unwrap_or_default(),
+    };
+    find_project_root_within(dir, &ceilings)
+}
+
+fn find_project_root_within(dir: &Path, ceilings: &[PathBuf]) -> Option<PathBuf> {
+    for ancestor in dir.ancestors() {
+        if ancestor.join(ID_FILENAME).is_file() {
+            return Some(ancestor.to_path_buf());
+        }
+        if ceilings.iter().any(|v| v == ancestor) {
+            return None;
+        }
+    }
+    None
+}
+
+/// Records `pwd` as the checkout of the project, so that `gc` can tell when it is gone.
+/// It is kept out of the config so that it can be read without the passphrase.
+///
+/// # Errors
+///
+/// Will return `Err` if it fails to write the checkout file
+pub fn record_checkout(project_dir: &Path, id: &str, pwd: &Path) -> Result<(), Error> {
+    let checkout_path = project_dir.join(id).join(CHECKOUT_FILENAME);
+    let pwd = pwd.to_string_lossy();
+    if fs::read_to_string(&checkout_path).ok().as_deref() == Some(&pwd) {
+        return Ok(());
+    }
+    write_atomic(&checkout_path, pwd.as_bytes())
+}
+
+#[must_use]
+pub fn read_checkout(project_dir: &Path, id: &str) -> Option<PathBuf> {
+    fs::read_to_string(project_dir.join(id).join(CHECKOUT_FILENAME))
+        .ok()
+        .map(PathBuf::from)
+}
+
+/// Reads the config of a project. A config of a previous version of rpilot is migrated to
+/// JSON, and the original is kept as `LEGACY_CONFIG_FILENAME`.
+///
+/// # Errors
+///
+/// Will return `Err` if the config file is missing or can not be read, or if the config file of an encrypted project can not be decrypted
+pub fn read_config(
+    project_dir: &Path,
+    id: &str,
+    cipher: Option<&Cipher>,
+) -> Result<(PathBuf, Project), Error> {
+    debug!("Reading the config file for this project");
+    let data_dir = project_dir.join(id);
+    let config_path = data_dir.join(CONFIG_FILENAME);
+
+    if config_path.to_str().unwrap_or("").is_empty() {
+        return Err(Error::other("config path is empty"));
+    }
+
+    let stored = fs::read(&config_path).map_err(|e| {
+        Error::new(
+            e.kind(),
+            format!(
+                "{} can not be read ({}). {}",
+                config_path.display(),
+                e,
+                CONFIG_RECOVERY_HINT
+            ),
+        )
+    })?;
+    let content = decrypt_if_needed(stored.clone(), cipher)?;
+    let (mut project, format) = config::decode(&content).map_err(|e| {
+        Error::new(
+            e.kind(),
+            format!(
+                "{} is not a valid config: {}. {}",
+                config_path.display(),
+                e,
+                CONFIG_RECOVERY_HINT
+            ),
+        )
+    })?;
+
+    let stored_key = std::mem::take(&mut project.hmac_key);
+    let _lock = if format == config::Format::Legacy || !stored_key.is_empty() {
+        // Read-only commands migrate the config too, so they take the lock before they
+        // write it, and read the config again if another command got there first
+        let lock = lock_project(project_dir, id).map_err(|e| Error::other(e.to_string()))?;
+        if fs::read(&config_path)? != stored {
+            return read_config(project_dir, id, cipher);
+        }
+        Some(lock)
+    } else {
+        None
+    };
+
+    // The key is kept out of the config, which anyone who can edit the profiles can read
+    let key_dir = get_key_dir()?;
+    if !stored_key.is_empty() && read_hmac_key(&key_dir, id)?.is_none() {
+        write_hmac_key(&key_dir, id, &stored_key)?;
+    }
+    project.hmac_key = read_hmac_key(&key_dir, id)?.unwrap_or_default();
+
+    if format == config::Format::Legacy {
+        let legacy_path = data_dir.join(LEGACY_CONFIG_FILENAME);
+        write_atomic(&legacy_path, &stored)?;
+        // Released versions did not update the hash when a profile was edited
+        let hashes: Vec<Option<String>> = project
+            .entries
+            .iter()
+            .map(|entry| {
+                fs::read(data_dir.join(&entry.id))
+                    .ok()
+                    .map(|env| project.hash_env(&env))
+            })
+            .collect();
+        for (entry, hash) in project.entries.iter_mut().zip(hashes) {
+            if let Some(hash) = hash {
+                entry.hash = hash;
+            }
+        }
+        save_config(&project, &config_path, cipher)?;
+        info!("Re-established the hashes of the profiles from their stored files");
+        info!(
+            "Migrated the config of this project to JSON. The previous one is kept at {}",
+            legacy_path.display()
+        );
+    } else if !stored_key.is_empty() {
+        save_config(&project, &config_path, cipher)?;
+        info!(
+            "Moved the HMAC key of this project to {}",
+            key_dir.display()
+        );
+    }
+
+    Ok((config_path, project))
+}
+
+/// Reads a profile after checking it against the hash recorded in its entry
+///
+/// # Errors
+///
+/// Will return `Err` if the stored file does not match its hash, or if the env file of an encrypted project can not be decrypted
+pub fn read_env(
+    project_dir: &Path,
+    project_id: &str,
+    project: &Project,
+    entry: &Entry,
+    cipher: Option<&Cipher>,
+) -> Result<(PathBuf, Option<String>), CommandError> {
+    let env_path = project_dir.join(project_id).join(&entry.id);
+    let stored = match fs::read(&env_path) {
+        Ok(v) => v,
+        Err(_) => return Ok((env_path, None)),
+    };
+    if project.hash_env(&stored) != entry.hash {
+        return Err(CommandError::Tampered(entry.name.clone()));
+    }
+
+    let env = decrypt_if_needed(stored, cipher).map_err(CommandError::Encryption)?;
+    Ok((env_path, Some(String::from_utf8_lossy(&env).into_owned())))
+}
+
+/// # Errors
+///
+/// Will return `Err` if the stored file of the profile exists but can not be read
+pub fn check_env(
+    project_dir: &Path,
+    project_id: &str,
+    project: &Project,
+    entry: &Entry,
+) -> Result<Integrity, Error> {
+    let env_path = project_dir.join(project_id).join(&entry.id);
+    match fs::read(env_path) {
+        Ok(v) if project.hash_env(&v) == entry.hash => Ok(Integrity::Intact),
+        Ok(_) => Ok(Integrity::Modified),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Integrity::Missing),
+        Err(e) => Err(e),
+    }
+}
+
+/// Reads a file written by `write_env`
+///
+/// # Errors
+///
+/// Will return `Err` if the file of an encrypted project can not be decrypted
+pub fn read_env_file(path: &Path, cipher: Option<&Cipher>) -> Result<Option<String>, Error> {
+    let env = match fs::read(path) {
+        Ok(v) => decrypt_if_needed(v, cipher)?,
+        Err(_) => return Ok(None),
+    };
+    Ok(Some(String::from_utf8_lossy(&env).into_owned()))
+}
+
+/// Writes the content of a profile, encrypting it when the project is encrypted.
+/// Returns the bytes which were stored.
+///
+/// # Errors
+///
+/// Will return `Err` if it fails to encrypt or to write the env file
+pub fn write_env(env_path: &Path, env: &str, cipher: Option<&Cipher>) -> Result<Vec<u8>, Error> {
+    let stored = match cipher {
+        Some(c) => c.encrypt(env.as_bytes())?,
+        None => env.as_bytes().to_vec(),
+    };
+    // A `.env` applied as a hard link only follows the profile while they are the same
+    // file, so a linked profile is written in place instead of being replaced
+    match fs::metadata(env_path) {
+        Ok(v) if v.nlink() > 1 => {
+            let mut file = fs::OpenOptions::new()
+                .write(true)
+                .truncate(true)
+                .open(env_path)?;
+            file.write_all(&stored)?;
+            file.sync_all()?;
+        }
+        _ => write_atomic(env_path, &stored)?,
+    }
+    Ok(stored)
+}
+
+/// Writes the content of the profile `name` and records its new hash.
+/// The config has to be saved by the caller.
+///
+/// # Errors
+///
+/// Will return `Err` if the profile does not exist or it fails to write the env file
+pub fn write_profile(
+    project_dir: &Path,
+    project_id: &str,
+    project: &mut Project,
+    name: &str,
+    env: &str,
+    cipher: Option<&Cipher>,
+) -> Result<(), Error> {
+    let index = project
+        .entries
+        .iter()
+        .position(|entry| entry.name == name)
+        .ok_or_else(|| Error::new(std::io::ErrorKind::NotFound, "the profile does not exist"))?;
+    let env_path = project_dir
+        .join(project_id)
+        .join(&project.entries[index].id);
+    let stored = write_env(&env_path, env, cipher)?;
+    project.entries[index].hash = project.hash_env(&stored);
+    Ok(())
+}
+
+/// SHA-256 of `content` encoded in base64
+#[must_use]
+pub fn hash_content(content: &[u8]) -> String {
+    base64::encode(digest(&SHA256, content).as_ref())
+}
+
+/// # Errors
+///
+/// Will return `Err` if it fails to retrieve the path of the config file or if it fails to save the config file
+pub fn save_config(
+    project: &Project,
+    config_path: &Path,
+    cipher: Option<&Cipher>,
+) -> Result<(), Error> {
+    if config_path.to_str().unwrap_or("").is_empty() {
+        return Err(Error::other("Empty config path"));
+    }
+
+    let content = config::encode(project)?;
+    let content = match cipher {
+        Some(c) => c.encrypt(&content)?,
+        None => content,
+    };
+    write_atomic(config_path, &content)
+}
+
+#[must_use]
+pub fn is_interactive() -> bool {
+    stdin().is_terminal()
+}
+
+/// Prints `message` and reads one line from stdin without its line break.
+/// Returns `None` when stdin is closed before a line is entered.
+///
+/// # Errors
+///
+/// Will return `Err` if it fails to read from stdin
+pub fn prompt(message: &str) -> Result<Option<String>, Error> {
+    print!("{}", message);
+    stdout().flush()?;
+
+    let mut buffer = String::new();
+    if stdin().read_line(&mut buffer)? == 0 {
+        println!();
+        return Ok(None);
+    }
+    Ok(Some(buffer.trim_end_matches(&['\r', '\n'][..]).to_string()))
+}
+
+/// Re-encrypts the config, every profile, revision and backup of a project with a key derived
+/// from `passphrase`. `current` is the key the data is encrypted with today, or `None` when
+/// the project is not encrypted yet. Everything is decrypted before anything is written, and
+/// the files are swapped together with the encryption file by `replace_all`, so the project
+/// never ends up with files encrypted with different keys. Profiles are replaced rather than
+/// written in place, so a `.env` which was hard linked to one of them keeps its old content.
+///
+/// # Errors
+///
+/// Will return `Err` if any of the files can not be decrypted with `current`, or if it fails to write them back
+pub fn change_passphrase(
+    project_dir: &Path,
+    id: &str,
+    current: Option<&Cipher>,
+    passphrase: &str,
+) -> Result<Cipher, CommandError> {
+    let (config_path, mut project) =
+        read_config(project_dir, id, current).map_err(CommandError::Encryption)?;
+    let mut envs = Vec::new();
+    let mut revisions = Vec::new();
+    for (entry_index, entry) in project.entries.iter().enumerate() {
+        if let (_, Some(env)) = read_env(project_dir, id, &project, entry, current)? {
+            envs.push((entry_index, env));
+        }
+        for (revision_index, revision) in entry.history.iter().enumerate() {
+            let env = read_revision(project_dir, id, &project, entry, revision.number, current)?;
+            revisions.push((entry_index, revision_index, env));
+        }
+    }
+    let mut backups = Vec::new();
+    for backup_id in list_backups(project_dir, id)? {
+        if let (path, Some(env)) =
+            read_backup(project_dir, id, &backup_id, current).map_err(CommandError::Encryption)?
+        {
+            backups.push((path, env));
+        }
+    }
+
+    let (cipher, params) = new_cipher(passphrase).map_err(CommandError::Encryption)?;
+    let mut files = Vec::new();
+    for (entry_index, env) in envs {
+        let stored = cipher.encrypt(env.as_bytes())?;
+        project.entries[entry_index].hash = project.hash_env(&stored);
+        files.push((
+            project_dir.join(id).join(&project.entries[entry_index].id),
+            stored,
+        ));
+    }
+    for (entry_index, revision_index, env) in revisions {
+        let stored = cipher.encrypt(env.as_bytes())?;
+        let hash = project.hash_env(&stored);
+        let entry = &mut project.entries[entry_index];
+        entry.history[revision_index].hash = hash;
+        let number = entry.history[revision_index].number;
+        files.push((revision_path(project_dir, id, entry, number), stored));
+    }
+    for (path, env) in backups {
+        files.push((path, cipher.encrypt(env.as_bytes())?));
+    }
+    files.push((config_path, cipher.encrypt(&config::encode(&project)?)?));
+    files.push((project_dir.join(id).join(ENCRYPTION_FILENAME), params));
+    replace_all(&project_dir.join(id).join(JOURNAL_FILENAME), &files)?;
+    Ok(cipher)
+}
+
+fn decrypt_if_needed(content: Vec<u8>, cipher: Option<&Cipher>) -> Result<Vec<u8>, Error> {
+    match cipher {
+        Some(c) => c.decrypt(&content),
+        None => Ok(content),
+    }
+}
+
+/// # Errors
+///
+/// Will return `Err` if it fails to find the appropriate entry in the project
+pub fn select_profile<'a>(project: &'a Project, name: &str) -> Result<&'a Entry, Error> {
+    match project.entries.iter().position(|entry| entry.name == name) {
+        Some(ind) => Ok(&project.entries[ind]),
+        None => Err(Error::other("Failed at getting the specified profile")),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use savefile::save_to_mem;
+    use tempdir::TempDir;
+
+    // Layout of the config of the released versions, see `config::LEGACY_CONFIG_VERSION`
+    #[derive(Savefile)]
+    struct EntryV0 {
+        name: String,
+        hash: String,
+        id: String,
+    }
+
+    #[derive(Savefile)]
+    struct ProjectV0 {
+        entries: Vec<EntryV0>,
+        current_profile: Box<Option<String>>,
+    }
+
+    #[test]
+    fn test_get_data_dir() {
+        let proj = data_dir_from(None).unwrap();
+        let target = proj.to_str().unwrap();
+        // Linux only contains the last word of project dir
+        assert_eq!(target.contains("rp"), true);
+
+        let home = data_dir_from(Some(OsString::from("/secure/rpilot"))).unwrap();
+        assert_eq!(home, PathBuf::from("/secure/rpilot"));
+        assert_eq!(data_dir_from(Some(OsString::new())).unwrap(), proj);
+    }
+
+    #[test]
+    fn test_normalize_path() {
+        assert_eq!(
+            normalize_path(Path::new("/src/project/./sub/../../data")),
+            PathBuf::from("/src/data")
+        );
+        assert_eq!(normalize_path(Path::new("/..")), PathBuf::from("/"));
+    }
+
+    #[test]
+    fn test_get_project_id() {
+        let tmp_dir = TempDir::new("test_check_if_initialized").unwrap();
+        let tmp_dir_path = tmp_dir.path().to_owned();
+        let project_id = get_project_id(&tmp_dir_path);
+
+        assert_eq!(project_id, None);
+
+        let config = tmp_dir_path.join(ID_FILENAME);
+        fs::write(config, "1234").unwrap();
+        let project_id = get_project_id(&tmp_dir_path);
+        assert_eq!(project_id.is_some(), true);
+    }
+
+    #[test]
+    fn test_find_project_root() {
+        let tmp_dir = TempDir::new("test_find_project_root").unwrap();
+        let tmp_dir_path = tmp_dir.path().to_owned();
+        let home = tmp_dir_path.join("home");
+        let src = home.join("project").join("src");
+        fs::create_dir_all(&src).unwrap();
+        let ceilings = vec![home.clone()];
+        assert_eq!(find_project_root_within(&src, &ceilings), None);
+
+        fs::write(tmp_dir_path.join(ID_FILENAME), "outside").unwrap();
+        assert_eq!(find_project_root_within(&src, &ceilings), None);
+        assert_eq!(
+            find_project_root_within(&src, &[]),
+            Some(tmp_dir_path.clone())
+        );
+
+        fs::write(home.join("project").join(ID_FILENAME), "1234").unwrap();
+        assert_eq!(
+            find_project_root_within(&src, &ceilings),
+            Some(home.join("project"))
+        );
+    }
+
+    #[test]
+    fn test_record_checkout() {
+        let tmp_dir = TempDir::new("test_record_checkout").unwrap();
+        let tmp_dir_path = tmp_dir.path().to_owned();
+        assert_eq!(read_checkout(&tmp_dir_path, ""), None);
+
+        record_checkout(&tmp_dir_path, "", Path::new("/src/project")).unwrap();
+        assert_eq!(
+            read_checkout(&tmp_dir_path, ""),
+            Some(PathBuf::from("/src/project"))
+        );
+    }
+
+    #[test]
+    fn test_save_config() {
+        let tmp_dir = TempDir::new("test_insert_new_entry").unwrap();
+        let tmp_dir_path = tmp_dir.path().to_owned();
+        let config = tmp_dir_path.join(CONFIG_FILENAME);
+
+        let entry = Entry {
+            name: "test".to_string(),
+            hash: "test hash".to_string(),
+            id: "test id".to_string(),
+            ..Default::default()
+        };
+        let project = Project {
+            entries: vec![entry],
+            current_profile: Box::new(Some("test".to_string())),
+            ..Default::default()
+        };
+        assert_eq!(save_config(&project, &config, None).is_ok(), true);
+    }
+
+    #[test]
+    fn test_save_config_and_read_config() {
+        let tmp_dir = TempDir::new("test_insert_new_entry").unwrap();
+        let tmp_dir_path = tmp_dir.path().to_owned();
+        let config = tmp_dir_path.join(CONFIG_FILENAME);
+
+        let entry = Entry {
+            name: "test".to_string(),
+            hash: "test hash".to_string(),
+            id: "test id".to_string(),
+            ..Default::default()
+        };
+        let project = Project {
+            entries: vec![entry],
+            current_profile: Box::new(None),
+            ..Default::default()
+        };
+        assert_eq!(save_config(&project, &config, None).is_ok(), true);
+
+        let (read_config, project) = read_config(&tmp_dir_path, "", None).unwrap();
+        assert_eq!(project.entries.len(), 1);
+        assert_eq!(project.entries[0].name, "test");
+        assert_eq!(read_config.to_str(), config.to_str());
+    }
+
+    #[test]
+    fn test_save_config_and_read_config_encrypted() {
+        let tmp_dir = TempDir::new("test_encrypted_config").unwrap();
+        let tmp_dir_path = tmp_dir.path().to_owned();
+        let config = tmp_dir_path.join(CONFIG_FILENAME);
+        let cipher = create_cipher(&tmp_dir_path, "", "secret").unwrap();
+
+        let project = Project {
+            entries: Vec::new(),
+            current_profile: Box::new(Some("test".to_string())),
+            ..Default::default()
+        };
+        save_config(&project, &config, Some(&cipher)).unwrap();
+        assert!(is_encrypted(&fs::read(&config).unwrap()));
+
+        let (_, project) = read_config(&tmp_dir_path, "", Some(&cipher)).unwrap();
+        assert_eq!(*project.current_profile, Some("test".to_string()));
+    }
+
+    #[test]
+    fn test_read_config_of_previous_version() {
+        let tmp_dir = TempDir::new("test_read_config_of_previous_version").unwrap();
+        let tmp_dir_path = tmp_dir.path().to_owned();
+        let project = ProjectV0 {
+            entries: vec![EntryV0 {
+                name: "dev".to_string(),
+                hash: hash_content(b"A=1\n"),
+                id: "dev id".to_string(),
+            }],
+            current_profile: Box::new(Some("dev".to_string())),
+        };
+        let content = save_to_mem(LEGACY_CONFIG_VERSION, &project).unwrap();
+        fs::write(tmp_dir_path.join(CONFIG_FILENAME), content).unwrap();
+        fs::write(tmp_dir_path.join("dev id"), "A=1\n").unwrap();
+
+        let (_, project) = read_config(&tmp_dir_path, "", None).unwrap();
+        assert_eq!(project.entries[0].name, "dev");
+        assert_eq!(*project.current_profile, Some("dev".to_string()));
+        assert!(!project.auto_confirm);
+        assert_eq!(project.apply_mode, ApplyMode::Symlink);
+
+        // The config was migrated to JSON once, and the original was kept
+        let migrated = fs::read_to_string(tmp_dir_path.join(CONFIG_FILENAME)).unwrap();
+        assert!(migrated.contains("\"current_profile\": \"dev\""));
+        assert!(tmp_dir_path.join(LEGACY_CONFIG_FILENAME).is_file());
+        let (_, project) = read_config(&tmp_dir_path, "", None).unwrap();
+        assert_eq!(project.entries[0].id, "dev id");
+    }
+
+    #[test]
+    fn test_read_config_of_legacy_edited_profile() {
+        let tmp_dir = TempDir::new("test_read_config_of_legacy_edited_profile").unwrap();
+        let tmp_dir_path = tmp_dir.path().to_owned();
+        let project = ProjectV0 {
+            entries: vec![EntryV0 {
+                name: "dev".to_string(),
+                hash: hash_content(b"A=1\n"),
+                id: "dev id".to_string(),
+            }],
+            current_profile: Box::new(Some("dev".to_string())),
+        };
+        fs::write(
+            tmp_dir_path.join(CONFIG_FILENAME),
+            save_to_mem(LEGACY_CONFIG_VERSION, &project).unwrap(),
+        )
+        .unwrap();
+        // Released versions of `edit` did not update the stored hash
+        fs::write(tmp_dir_path.join("dev id"), "A=2\n").unwrap();
+
+        let (_, project) = read_config(&tmp_dir_path, "", None).unwrap();
+        let entry = &project.entries[0];
+        let (_, env) = read_env(&tmp_dir_path, "", &project, entry, None).unwrap();
+        assert_eq!(env, Some("A=2\n".to_string()));
+    }
+
+    #[test]
+    fn test_read_config_fails_on_unreadable_config() {
+        let tmp_dir = TempDir::new("test_read_config_fails_on_unreadable_config").unwrap();
+        let tmp_dir_path = tmp_dir.path().to_owned();
+        assert!(read_config(&tmp_dir_path, "", None).is_err());
+
+        fs::write(tmp_dir_path.join(CONFIG_FILENAME), "{\"version\": 1,").unwrap();
+        let message = read_config(&tmp_dir_path, "", None)
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(message.contains("is not a valid config"));
+        assert!(message.contains("run init to start over"));
+    }
+
+    #[test]
+    fn test_change_passphrase() {
+        let tmp_dir = TempDir::new("test_change_passphrase").unwrap();
+        let tmp_dir_path = tmp_dir.path().to_owned();
+        let data_dir = tmp_dir_path.join("project");
+        fs::create_dir(&data_dir).unwrap();
+        let mut project = Project {
+            entries: vec![Entry {
+                name: "dev".to_string(),
+                id: "dev id".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        write_profile(&tmp_dir_path, "project", &mut project, "dev", "A=1", None).unwrap();
+        record_revision(&tmp_dir_path, "project", &mut project, "dev", "").unwrap();
+        write_profile(&tmp_dir_path, "project", &mut project, "dev", "A=2", None).unwrap();
+        save_config(&project, &data_dir.join(CONFIG_FILENAME), None).unwrap();
+        let backup_id = backup_env(&tmp_dir_path, "project", "LOCAL=1", None).unwrap();
+
+        let first = change_passphrase(&tmp_dir_path, "project", None, "first").unwrap();
+        let cipher = change_passphrase(&tmp_dir_path, "project", Some(&first), "second").unwrap();
+        assert!(read_config(&tmp_dir_path, "project", Some(&first)).is_err());
+        let (_, project) = read_config(&tmp_dir_path, "project", Some(&cipher)).unwrap();
+        let entry = &project.entries[0];
+        let (_, env) = read_env(&tmp_dir_path, "project", &project, entry, Some(&cipher)).unwrap();
+        assert_eq!(env, Some("A=2".to_string()));
+        let env = read_revision(&tmp_dir_path, "project", &project, entry, 1, Some(&cipher));
+        assert_eq!(env.unwrap(), "A=1");
+        let (path, env) = read_backup(&tmp_dir_path, "project", &backup_id, Some(&cipher)).unwrap();
+        assert_eq!(env, Some("LOCAL=1".to_string()));
+        assert!(is_encrypted(&fs::read(path).unwrap()));
+
+        // Nothing is left of the swap
+        assert!(!data_dir.join(JOURNAL_FILENAME).exists());
+        assert!(fs::read_dir(&data_dir).unwrap().all(|v| !v
+            .unwrap()
+            .file_name()
+            .to_string_lossy()
+            .ends_with(".staged")));
+    }
+
+    #[test]
+    fn test_apply_mode_from_str() {
+        assert_eq!("copy".parse::<ApplyMode>().unwrap(), ApplyMode::Copy);
+        assert_eq!(
+            ApplyMode::Hardlink
+                .to_string()
+                .parse::<ApplyMode>()
+                .unwrap(),
+            ApplyMode::Hardlink
+        );
+        assert!("move".parse::<ApplyMode>().is_err());
+    }
+
+    #[test]
+    fn test_select_profile() {
+        let project = Project {
+            entries: vec![
+                Entry {
+                    name: "first".to_string(),
+                    hash: "test".to_string(),
+                    id: "first id".to_string(),
+                    ..Default::default()
+                },
+                Entry {
+                    name: "second".to_string(),
+                    hash: "test".to_string(),
+                    id: "second id".to_string(),
+                    ..Default::default()
+                },
+            ],
+            current_profile: Box::new(None),
+            ..Default::default()
+        };
+
+        let profile = select_profile(&project, "first").unwrap();
+        assert_eq!(profile.id, "first id");
+    }
+}
@@ -0,0 +1,280 @@
+use regex::Regex;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::result::Result;
+
+use super::EnvFile;
+use crate::error::Error as CommandError;
+
+/// Schema with a type for every key. Takes precedence over `EXAMPLE_FILENAME`.
+pub const SCHEMA_FILENAME: &str = ".env.schema";
+/// Example env file whose keys are all required
+pub const EXAMPLE_FILENAME: &str = ".env.example";
+
+/// The keys which a profile is expected to define
+#[derive(Debug)]
+pub struct Schema {
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug)]
+struct Rule {
+    key: String,
+    kind: Kind,
+    optional: bool,
+}
+
+#[derive(Debug)]
+enum Kind {
+    String,
+    Url,
+    Port,
+    Bool,
+    Int,
+    Enum(Vec<String>),
+    /// The compiled pattern, anchored to the whole value, and the pattern as it was written
+    Regex(Regex, String),
+}
+
+/// A way in which a profile does not match its schema
+#[derive(Debug, PartialEq)]
+pub enum Issue {
+    Missing(String),
+    Unknown(String),
+    Invalid(String, String),
+}
+
+impl Issue {
+    /// Unknown keys are only errors in strict mode
+    #[must_use]
+    pub fn is_error(&self, strict: bool) -> bool {
+        strict || !matches!(self, Issue::Unknown(_))
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Issue::Missing(key) => write!(f, "{} is missing", key),
+            Issue::Unknown(key) => write!(f, "{} is not in the schema", key),
+            Issue::Invalid(key, expected) => write!(f, "{} {}", key, expected),
+        }
+    }
+}
+
+impl Schema {
+    /// Reads the schema of the project checked out at `dir`. Returns `None` when the
+    /// project has neither a schema nor an example file.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the file can not be read or is not a valid schema
+    pub fn load(dir: &Path) -> Result<Option<Schema>, CommandError> {
+        let schema_path = dir.join(SCHEMA_FILENAME);
+        if schema_path.exists() {
+            let schema = Schema::parse(&fs::read_to_string(&schema_path)?)
+                .map_err(|e| CommandError::InvalidSchema(SCHEMA_FILENAME.to_string(), e))?;
+            return Ok(Some(schema));
+        }
+        let example_path = dir.join(EXAMPLE_FILENAME);
+        if example_path.exists() {
+            let example = EnvFile::parse(&fs::read_to_string(&example_path)?)?;
+            return Ok(Some(Schema::from_example(&example)));
+        }
+        Ok(None)
+    }
+
+    /// Every key of an example file is a required string
+    #[must_use]
+    pub fn from_example(example: &EnvFile) -> Schema {
+        Schema {
+            rules: example
+                .variables()
+                .map(|v| Rule {
+                    key: v.key.clone(),
+                    kind: Kind::String,
+                    optional: false,
+                })
+                .collect(),
+        }
+    }
+
+    /// Parses a schema file. It uses the syntax of an env file, and the value of each key is
+    /// its type: `string`, `url`, `port`, `bool`, `int`, `enum:a,b,c` or `regex:PATTERN`,
+    /// optionally prefixed by `optional `.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` with a message if a line or a type is not valid
+    pub fn parse(input: &str) -> Result<Schema, String> {
+        let env = EnvFile::parse(input).map_err(|e| e.to_string())?;
+        let mut rules = Vec::new();
+        for variable in env.variables() {
+            let (optional, spec) = match variable.value.strip_prefix("optional ") {
+                Some(v) => (true, v.trim_start()),
+                None => (false, variable.value.as_str()),
+            };
+            let kind = parse_kind(spec).map_err(|e| format!("{}: {}", variable.key, e))?;
+            rules.push(Rule {
+                key: variable.key.clone(),
+                kind,
+                optional,
+            });
+        }
+        Ok(Schema { rules })
+    }
+
+    /// Lists the required keys which `env` does not define, the keys which the schema does
+    /// not know about and the values which do not have the expected type
+    #[must_use]
+    pub fn check(&self, env: &EnvFile) -> Vec<Issue> {
+        let mut issues = Vec::new();
+        for rule in &self.rules {
+            match env.get(&rule.key) {
+                Some(value) => {
+                    if let Some(expected) = rule.kind.validate(value) {
+                        issues.push(Issue::Invalid(rule.key.clone(), expected));
+                    }
+                }
+                None if !rule.optional => issues.push(Issue::Missing(rule.key.clone())),
+                None => (),
+            }
+        }
+
+        let mut seen = Vec::new();
+        for variable in env.variables() {
+            if seen.contains(&variable.key) {
+                continue;
+            }
+            seen.push(variable.key.clone());
+            if !self.rules.iter().any(|rule| rule.key == variable.key) {
+                issues.push(Issue::Unknown(variable.key.clone()));
+            }
+        }
+        issues
+    }
+}
+
+fn parse_kind(spec: &str) -> Result<Kind, String> {
+    let (name, argument) = match spec.find(':') {
+        Some(ind) => (&spec[..ind], Some(&spec[ind + 1..])),
+        None => (spec, None),
+    };
+    match (name, argument) {
+        ("string", None) | ("", None) => Ok(Kind::String),
+        ("url", None) => Ok(Kind::Url),
+        ("port", None) => Ok(Kind::Port),
+        ("bool", None) => Ok(Kind::Bool),
+        ("int", None) => Ok(Kind::Int),
+        ("enum", Some(v)) if !v.is_empty() => Ok(Kind::Enum(
+            v.split(',').map(|v| v.trim().to_string()).collect(),
+        )),
+        ("regex", Some(v)) => Regex::new(&format!("^(?:{})$", v))
+            .map(|regex| Kind::Regex(regex, v.to_string()))
+            .map_err(|e| e.to_string()),
+        _ => Err(format!(
+            "{} is not a valid type. Use string, url, port, bool, int, enum:a,b or regex:PATTERN",
+            spec
+        )),
+    }
+}
+
+impl Kind {
+    /// Returns what was expected when `value` does not have this type
+    fn validate(&self, value: &str) -> Option<String> {
+        match self {
+            Kind::Url if !is_url(value) => Some("is not a URL".to_string()),
+            Kind::Port if !matches!(value.parse::<u16>(), Ok(v) if v > 0) => {
+                Some("is not a port number".to_string())
+            }
+            Kind::Bool
+                if !["true", "false", "1", "0", "yes", "no"]
+                    .contains(&value.to_ascii_lowercase().as_str()) =>
+            {
+                Some("is not true or false".to_string())
+            }
+            Kind::Int if value.parse::<i64>().is_err() => Some("is not an integer".to_string()),
+            Kind::Enum(values) if !values.iter().any(|v| v == value) => {
+                Some(format!("is not one of {}", values.join(", ")))
+            }
+            Kind::Regex(regex, pattern) if !regex.is_match(value) => {
+                Some(format!("does not match {}", pattern))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A scheme followed by `://` and a non-empty rest without whitespace
+fn is_url(value: &str) -> bool {
+    match value.find("://") {
+        Some(ind) => {
+            let (scheme, rest) = (&value[..ind], &value[ind + 3..]);
+            scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                && scheme
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+                && !rest.is_empty()
+                && !rest.contains(char::is_whitespace)
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_check_against_schema() {
+        let schema = Schema::parse(
+            "DATABASE_URL=url\n\
+             PORT=port\n\
+             DEBUG=optional bool\n\
+             LEVEL=enum:debug,info\n\
+             WORKERS=int\n\
+             TOKEN='regex:[a-f0-9]{4}'\n",
+        )
+        .unwrap();
+        let env = EnvFile::parse(
+            "DATABASE_URL=postgres://localhost/app\n\
+             PORT=70000\n\
+             LEVEL=trace\n\
+             TOKEN=abcd1\n\
+             EXTRA=1\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            schema.check(&env),
+            vec![
+                Issue::Invalid("PORT".to_string(), "is not a port number".to_string()),
+                Issue::Invalid("LEVEL".to_string(), "is not one of debug, info".to_string()),
+                Issue::Missing("WORKERS".to_string()),
+                Issue::Invalid(
+                    "TOKEN".to_string(),
+                    "does not match [a-f0-9]{4}".to_string()
+                ),
+                Issue::Unknown("EXTRA".to_string()),
+            ]
+        );
+        assert!(!Issue::Unknown("EXTRA".to_string()).is_error(false));
+        assert!(Issue::Unknown("EXTRA".to_string()).is_error(true));
+    }
+
+    #[test]
+    fn test_parse_invalid_schema() {
+        assert!(Schema::parse("A=uuid").is_err());
+        assert!(Schema::parse("A=enum:").is_err());
+        assert!(Schema::parse("A='regex:('").is_err());
+    }
+
+    #[test]
+    fn test_check_against_example() {
+        let example = EnvFile::parse("A=example\nB=\n").unwrap();
+        let schema = Schema::from_example(&example);
+        let env = EnvFile::parse("A=1").unwrap();
+        assert_eq!(schema.check(&env), vec![Issue::Missing("B".to_string())]);
+    }
+}
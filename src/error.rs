@@ -0,0 +1,157 @@
+use thiserror::Error as ThisError;
+
+use crate::common;
+
+/// Errors returned by every rpilot command.
+///
+/// Each variant maps to a stable process exit code so that scripts can tell
+/// failures apart. The codes are part of the public interface and must not change:
+///
+/// | code | meaning                                                     |
+/// |------|-------------------------------------------------------------|
+/// | 1    | invalid command line arguments                              |
+/// | 2    | the directory is not initialized for rpilot                 |
+/// | 3    | a profile, backup, variable or revision does not exist      |
+/// | 4    | the command was aborted                                     |
+/// | 5    | reading or writing a file failed                            |
+/// | 6    | the config of the project is corrupted                      |
+/// | 7    | the directory or the profile already exists                 |
+/// | 8    | the env file, its bases or its references are not valid     |
+/// | 9    | no profile has been applied                                 |
+/// | 10   | the profiles could not be encrypted or decrypted            |
+/// | 11   | a confirmation is needed but stdin is not a terminal        |
+/// | 12   | a stored profile was modified outside rpilot                |
+/// | 13   | a profile does not match the schema of the project          |
+/// | 14   | another rpilot is changing the project                      |
+/// | 126  | the command given to `run` could not be executed            |
+/// | 127  | the command given to `run` was not found                    |
+#[derive(ThisError, Debug)]
+pub enum Error {
+    #[error("reading .rpilot file failed. Make sure this project is initialised properly.")]
+    NotInitialized,
+
+    #[error("this directory is already initialized for rpilot")]
+    AlreadyInitialized,
+
+    #[error("the specified profile name does not exists for this project. Please make sure that you are passing the correct name.")]
+    NotExists,
+
+    #[error("The specified profile name already exists for this project. Please specify different name. If you want to modify the content of the existing env, then please use edit command.")]
+    AlreadyExists,
+
+    #[error("there is no backup of .env for this project")]
+    NoBackup,
+
+    #[error("the variable {0} does not exist in the profile")]
+    NoVariable(String),
+
+    #[error(
+        "the profile {0} has no revision {1}. Please run history command to see its revisions."
+    )]
+    NoRevision(String, u32),
+
+    #[error("the command was aborted")]
+    Aborted,
+
+    #[error(
+        "no profile has been applied yet. Please use apply command to use the certain profile"
+    )]
+    NoProfileIsApplied,
+
+    #[error("There was no valid env variables in the file")]
+    NoValidEnv,
+
+    #[error("The env file could not be parsed at {0}")]
+    InvalidEnv(#[from] common::ParseError),
+
+    #[error("the bases of the profiles form a cycle: {0}")]
+    InheritanceCycle(String),
+
+    #[error("Failed at interpolating the profile: {0}")]
+    Interpolation(#[from] common::InterpolationError),
+
+    #[error("{0} is not a valid schema: {1}")]
+    InvalidSchema(String, String),
+
+    #[error("{0} did not pass the check against the schema of this project. Please run check command to see why.")]
+    CheckFailed(String),
+
+    #[error("Failed at reading the config: {0}")]
+    CorruptConfig(std::io::Error),
+
+    #[error("the profiles of this project are already encrypted. Please use rotate-key command to change the passphrase.")]
+    AlreadyEncrypted,
+
+    #[error("the profiles of this project are not encrypted. Please use init --encrypt to encrypt them.")]
+    NotEncrypted,
+
+    #[error("Failed at unlocking the encrypted profiles: {0}")]
+    Encryption(std::io::Error),
+
+    #[error("{0}")]
+    NonInteractive(String),
+
+    #[error("the stored profile {0} was modified outside rpilot. Please check its content and run verify --accept to trust it.")]
+    Tampered(String),
+
+    #[error("the project is locked by {0}. Please try again once the other rpilot command has finished.")]
+    Locked(String),
+
+    #[error("{0}")]
+    InvalidArgument(String),
+
+    #[error("Failed at running {0}: {1}")]
+    ExecFail(String, std::io::Error),
+
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+}
+
+impl Error {
+    #[must_use]
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::NotInitialized => 2,
+            Error::NotExists | Error::NoBackup | Error::NoVariable(_) | Error::NoRevision(_, _) => {
+                3
+            }
+            Error::Aborted => 4,
+            Error::Io(_) => 5,
+            Error::CorruptConfig(_) => 6,
+            Error::AlreadyInitialized | Error::AlreadyExists => 7,
+            Error::InvalidEnv(_)
+            | Error::NoValidEnv
+            | Error::InheritanceCycle(_)
+            | Error::Interpolation(_)
+            | Error::InvalidSchema(_, _) => 8,
+            Error::NoProfileIsApplied => 9,
+            Error::Encryption(_) | Error::AlreadyEncrypted | Error::NotEncrypted => 10,
+            Error::NonInteractive(_) => 11,
+            Error::Tampered(_) => 12,
+            Error::CheckFailed(_) => 13,
+            Error::Locked(_) => 14,
+            Error::InvalidArgument(_) => 1,
+            Error::ExecFail(_, e) if e.kind() == std::io::ErrorKind::NotFound => 127,
+            Error::ExecFail(_, _) => 126,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::ErrorKind;
+
+    #[test]
+    fn test_exit_code() {
+        assert_eq!(Error::NotInitialized.exit_code(), 2);
+        assert_eq!(Error::NotExists.exit_code(), 3);
+        assert_eq!(Error::Aborted.exit_code(), 4);
+        assert_eq!(Error::Io(ErrorKind::Other.into()).exit_code(), 5);
+        assert_eq!(Error::CorruptConfig(ErrorKind::Other.into()).exit_code(), 6);
+        assert_eq!(
+            Error::ExecFail("cmd".to_string(), ErrorKind::NotFound.into()).exit_code(),
+            127
+        );
+    }
+}
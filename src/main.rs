@@ -1,41 +1,159 @@
+// The tests compare results with literal booleans
+#![cfg_attr(test, allow(clippy::bool_assert_comparison))]
+
 use env_logger::Env;
+use log::error;
+use std::env;
+use std::path::PathBuf;
+use std::process;
 use structopt::StructOpt;
 
 pub mod commands;
 pub mod common;
+pub mod error;
 use commands::add;
+use commands::allow;
 use commands::apply;
+use commands::check;
+use commands::clone;
+use commands::config;
 use commands::current;
+use commands::diff;
 use commands::edit;
+use commands::example;
+use commands::export;
+use commands::gc;
+use commands::get;
+use commands::history;
+use commands::hook;
 use commands::init;
 use commands::list;
+use commands::protect;
 use commands::remove;
+use commands::rename;
+use commands::restore_backup;
+use commands::rollback;
+use commands::rotate_key;
+use commands::run;
+use commands::set;
 use commands::show;
+use commands::status;
+use commands::unset;
+use commands::verify;
+
+#[derive(Debug, PartialEq, StructOpt)]
+struct Opt {
+    /// Run as if rpilot was started in this directory
+    #[structopt(short = "C", long, global = true, parse(from_os_str))]
+    project_dir: Option<PathBuf>,
+
+    /// Keep the config and profiles of the projects in this directory. Overrides RPILOT_HOME.
+    #[structopt(long, global = true, parse(from_os_str))]
+    data_dir: Option<PathBuf>,
+
+    #[structopt(subcommand)]
+    command: Rpilot,
+}
 
 #[derive(Debug, PartialEq, StructOpt)]
 enum Rpilot {
     Add(add::Args),
-    Init,
+    Init(init::Args),
     List,
     Current,
     Remove(remove::Args),
     Show(show::Args),
     Edit(edit::Args),
     Apply(apply::Args),
+    RotateKey,
+    Run(run::Args),
+    Config(config::Args),
+    Protect(protect::Args),
+    RestoreBackup(restore_backup::Args),
+    Status(status::Args),
+    Verify(verify::Args),
+    Gc(gc::Args),
+    Get(get::Args),
+    Set(set::Args),
+    Unset(unset::Args),
+    Diff(diff::Args),
+    Rename(rename::Args),
+    Clone(clone::Args),
+    Check(check::Args),
+    Example(example::Args),
+    Export(export::Args),
+    Hook(hook::Args),
+    Allow(allow::Args),
+    History(history::Args),
+    Rollback(rollback::Args),
 }
 
 fn main() {
     let env = Env::new().filter_or("LOG", "info");
     env_logger::init_from_env(env);
 
-    match Rpilot::from_args() {
+    let opt = Opt::from_args();
+    // Relative to where rpilot was started, not to -C, and resolved once so that links
+    // to the profiles are absolute
+    let data_dir = opt
+        .data_dir
+        .clone()
+        .or_else(|| env::var_os(common::HOME_ENV).map(PathBuf::from));
+    let key_dir = env::var_os(common::KEY_DIR_ENV).map(PathBuf::from);
+    for (name, dir) in &[(common::HOME_ENV, data_dir), (common::KEY_DIR_ENV, key_dir)] {
+        if let Some(dir) = dir.as_ref().filter(|v| !v.as_os_str().is_empty()) {
+            let dir = env::current_dir()
+                .map(|v| common::normalize_path(&v.join(dir)))
+                .unwrap_or_else(|_| dir.clone());
+            env::set_var(name, dir);
+        }
+    }
+    if let Some(dir) = &opt.project_dir {
+        if let Err(e) = env::set_current_dir(dir) {
+            let e = error::Error::from(e);
+            error!(
+                "Failed at changing the directory to {}: {}",
+                dir.display(),
+                e
+            );
+            process::exit(e.exit_code());
+        }
+    }
+
+    let result = match opt.command {
         Rpilot::Add(v) => add::execute(&v),
-        Rpilot::Init => init::execute(),
+        Rpilot::Init(v) => init::execute(&v),
         Rpilot::List => list::execute(),
         Rpilot::Current => current::execute(),
         Rpilot::Remove(v) => remove::execute(&v),
         Rpilot::Show(v) => show::execute(&v),
         Rpilot::Edit(v) => edit::execute(&v),
         Rpilot::Apply(v) => apply::execute(&v),
+        Rpilot::RotateKey => rotate_key::execute(),
+        Rpilot::Run(v) => run::execute(&v),
+        Rpilot::Config(v) => config::execute(&v),
+        Rpilot::Protect(v) => protect::execute(&v),
+        Rpilot::RestoreBackup(v) => restore_backup::execute(&v),
+        Rpilot::Status(v) => status::execute(&v),
+        Rpilot::Verify(v) => verify::execute(&v),
+        Rpilot::Gc(v) => gc::execute(&v),
+        Rpilot::Get(v) => get::execute(&v),
+        Rpilot::Set(v) => set::execute(&v),
+        Rpilot::Unset(v) => unset::execute(&v),
+        Rpilot::Diff(v) => diff::execute(&v),
+        Rpilot::Rename(v) => rename::execute(&v),
+        Rpilot::Clone(v) => clone::execute(&v),
+        Rpilot::Check(v) => check::execute(&v),
+        Rpilot::Example(v) => example::execute(&v),
+        Rpilot::Export(v) => export::execute(&v),
+        Rpilot::Hook(v) => hook::execute(&v),
+        Rpilot::Allow(v) => allow::execute(&v),
+        Rpilot::History(v) => history::execute(&v),
+        Rpilot::Rollback(v) => rollback::execute(&v),
     };
+
+    if let Err(e) = result {
+        error!("{}", e);
+        process::exit(e.exit_code());
+    }
 }
@@ -0,0 +1,929 @@
+//! Runs the built binary as a user would, against a temporary project and data dir.
+//! `add` and `edit` get a fake editor which writes `RPILOT_TEST_CONTENT` to the file.
+
+use fs2::FileExt;
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use tempdir::TempDir;
+
+struct Sandbox {
+    _tmp_dir: TempDir,
+    project: PathBuf,
+    data: PathBuf,
+    editor: PathBuf,
+}
+
+impl Sandbox {
+    fn new(name: &str) -> Sandbox {
+        let tmp_dir = TempDir::new(name).unwrap();
+        let project = tmp_dir.path().join("project");
+        let data = tmp_dir.path().join("data");
+        let editor = tmp_dir.path().join("editor.sh");
+        fs::create_dir(&project).unwrap();
+        fs::write(
+            &editor,
+            "#!/bin/sh\nprintf '%s' \"$RPILOT_TEST_CONTENT\" > \"$1\"\n",
+        )
+        .unwrap();
+        fs::set_permissions(&editor, fs::Permissions::from_mode(0o755)).unwrap();
+        Sandbox {
+            _tmp_dir: tmp_dir,
+            project,
+            data,
+            editor,
+        }
+    }
+
+    /// Creates a sandbox with an initialized project
+    fn init(name: &str) -> Sandbox {
+        let sandbox = Sandbox::new(name);
+        assert_success(&sandbox.run(&["init"]));
+        sandbox
+    }
+
+    fn command(&self, args: &[&str]) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_rpilot"));
+        command
+            .args(args)
+            .current_dir(&self.project)
+            .env("RPILOT_HOME", &self.data)
+            .env("RPILOT_KEY_DIR", self.data.with_file_name("keys"))
+            .env("RPILOT_CEILING_DIRECTORIES", self.project.parent().unwrap())
+            .env("EDITOR", &self.editor)
+            .env_remove("VISUAL")
+            .env_remove("RPILOT_PASSPHRASE")
+            .env_remove("RPILOT_NEW_PASSPHRASE")
+            .env_remove("RPILOT_HOOK_STATE")
+            .env_remove("RPILOT_LOCK_TIMEOUT");
+        command
+    }
+
+    fn run(&self, args: &[&str]) -> Output {
+        self.command(args).output().unwrap()
+    }
+
+    /// Runs a command which opens the editor, which saves `content`
+    fn run_with_editor(&self, args: &[&str], content: &str) -> Output {
+        self.command(args)
+            .env("RPILOT_TEST_CONTENT", content)
+            .output()
+            .unwrap()
+    }
+
+    fn add(&self, name: &str, content: &str) {
+        assert_success(&self.run_with_editor(&["add", "--name", name], content));
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.project.join(name)
+    }
+
+    fn read(&self, name: &str) -> String {
+        fs::read_to_string(self.path(name)).unwrap()
+    }
+
+    fn write(&self, name: &str, content: &str) {
+        fs::write(self.path(name), content).unwrap();
+    }
+
+    /// Directory of the project in the data dir
+    fn project_data(&self) -> PathBuf {
+        let id = fs::read_to_string(self.path(".rpilot")).unwrap();
+        self.data.join(id)
+    }
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).to_string()
+}
+
+fn assert_success(output: &Output) {
+    assert!(
+        output.status.success(),
+        "exited with {:?}\nstdout: {}\nstderr: {}",
+        output.status.code(),
+        stdout(output),
+        stderr(output)
+    );
+}
+
+fn assert_code(output: &Output, code: i32) {
+    assert_eq!(
+        output.status.code(),
+        Some(code),
+        "stdout: {}\nstderr: {}",
+        stdout(output),
+        stderr(output)
+    );
+}
+
+fn is_symlink(path: &Path) -> bool {
+    fs::symlink_metadata(path)
+        .map(|v| v.file_type().is_symlink())
+        .unwrap_or(false)
+}
+
+#[test]
+fn test_init() {
+    let sandbox = Sandbox::new("test_init");
+    assert_success(&sandbox.run(&["init"]));
+    assert!(sandbox.path(".rpilot").is_file());
+    assert!(sandbox.project_data().join("config").is_file());
+
+    assert_code(&sandbox.run(&["init"]), 7);
+}
+
+#[test]
+fn test_corrupted_config() {
+    let sandbox = Sandbox::init("test_corrupted_config");
+    sandbox.add("dev", "A=1\n");
+    let config = sandbox.project_data().join("config");
+    assert!(fs::read_to_string(&config)
+        .unwrap()
+        .contains("\"name\": \"dev\""));
+
+    fs::write(&config, "{\"version\": 1, \"entries\": ").unwrap();
+    let output = sandbox.run(&["list"]);
+    assert_code(&output, 6);
+    assert!(stderr(&output).contains("is not a valid config"));
+    assert_code(
+        &sandbox.run_with_editor(&["add", "--name", "prod"], "A=2\n"),
+        6,
+    );
+    assert_eq!(
+        fs::read_to_string(&config).unwrap(),
+        "{\"version\": 1, \"entries\": "
+    );
+}
+
+#[test]
+fn test_concurrent_commands_keep_every_change() {
+    let sandbox = Sandbox::init("test_concurrent_commands_keep_every_change");
+    sandbox.add("dev", "A=1\n");
+
+    let children = (0..8)
+        .map(|i| {
+            sandbox
+                .command(&["set", "--name", "dev", &format!("KEY_{}={}", i, i)])
+                .stderr(Stdio::null())
+                .spawn()
+                .unwrap()
+        })
+        .collect::<Vec<_>>();
+    for mut child in children {
+        assert!(child.wait().unwrap().success());
+    }
+    for i in 0..8 {
+        let output = sandbox.run(&["get", "--name", "dev", &format!("KEY_{}", i)]);
+        assert_eq!(stdout(&output), format!("{}\n", i));
+    }
+}
+
+#[test]
+fn test_locked_project() {
+    let sandbox = Sandbox::init("test_locked_project");
+    sandbox.add("dev", "A=1\n");
+    let lock_path = sandbox.project_data().join("lock");
+    let lock = fs::OpenOptions::new()
+        .write(true)
+        .truncate(true)
+        .open(&lock_path)
+        .unwrap();
+    lock.try_lock_exclusive().unwrap();
+    fs::write(&lock_path, "4242").unwrap();
+
+    let output = sandbox
+        .command(&["set", "--name", "dev", "A=2"])
+        .env("RPILOT_LOCK_TIMEOUT", "0")
+        .output()
+        .unwrap();
+    assert_code(&output, 14);
+    assert!(stderr(&output).contains("locked by pid 4242"));
+    let output = sandbox
+        .command(&["verify", "--hmac"])
+        .env("RPILOT_LOCK_TIMEOUT", "0")
+        .output()
+        .unwrap();
+    assert_code(&output, 14);
+    assert_success(&sandbox.run(&["get", "--name", "dev", "A"]));
+
+    lock.unlock().unwrap();
+    assert_success(&sandbox.run(&["set", "--name", "dev", "A=2"]));
+    assert_eq!(stdout(&sandbox.run(&["get", "--name", "dev", "A"])), "2\n");
+}
+
+#[test]
+fn test_locked_data_dir() {
+    let sandbox = Sandbox::init("test_locked_data_dir");
+    let lock = fs::File::create(sandbox.data.join("lock")).unwrap();
+    lock.try_lock_exclusive().unwrap();
+
+    let output = sandbox
+        .command(&["allow"])
+        .env("RPILOT_LOCK_TIMEOUT", "0")
+        .output()
+        .unwrap();
+    assert_code(&output, 14);
+    lock.unlock().unwrap();
+    assert_success(&sandbox.run(&["allow"]));
+}
+
+#[test]
+fn test_editor_runs_without_lock() {
+    let sandbox = Sandbox::init("test_editor_runs_without_lock");
+    sandbox.add("dev", "A=1\n");
+    sandbox.write("new.env", "C=1\n");
+    // The editor runs another command, which would fail at once if the project was locked
+    let editor = sandbox.path("editor.sh");
+    fs::write(
+        &editor,
+        "#!/bin/sh\n$RPILOT_TEST_COMMAND && printf '%s' \"$RPILOT_TEST_CONTENT\" > \"$1\"\n",
+    )
+    .unwrap();
+    fs::set_permissions(&editor, fs::Permissions::from_mode(0o755)).unwrap();
+    let bin = env!("CARGO_BIN_EXE_rpilot");
+    let run = |args: &[&str], command: &str| {
+        sandbox
+            .command(args)
+            .env("EDITOR", &editor)
+            .env("RPILOT_TEST_CONTENT", "A=3\n")
+            .env("RPILOT_LOCK_TIMEOUT", "0")
+            .env("RPILOT_TEST_COMMAND", format!("{} {}", bin, command))
+            .output()
+            .unwrap()
+    };
+
+    let output = run(&["edit", "--name", "dev"], "set --name dev B=2");
+    assert_success(&output);
+    assert!(stderr(&output).contains("was changed while it was edited"));
+    assert_eq!(stdout(&sandbox.run(&["get", "--name", "dev", "A"])), "3\n");
+    let output = sandbox.run(&["diff", "--name", "dev", "--rev", "2", "--show-values"]);
+    assert!(stdout(&output).contains("-B=2"));
+
+    assert_success(&sandbox.run(&["set", "--name", "dev", "A=4"]));
+    let output = run(&["edit", "--name", "dev"], "remove --name dev");
+    assert_code(&output, 3);
+    let output = run(&["add", "--name", "new"], "add --name new --source new.env");
+    assert_code(&output, 7);
+    assert_eq!(stdout(&sandbox.run(&["get", "--name", "new", "C"])), "1\n");
+}
+
+#[test]
+fn test_commands_need_init() {
+    let sandbox = Sandbox::new("test_commands_need_init");
+    assert_code(&sandbox.run(&["list"]), 2);
+    assert_code(&sandbox.run(&["apply", "--name", "dev", "--yes"]), 2);
+    assert_code(&sandbox.run(&["status"]), 2);
+}
+
+#[test]
+fn test_add_list_show_and_remove() {
+    let sandbox = Sandbox::init("test_add_list_show_and_remove");
+    sandbox.add("dev", "A=1\n");
+    sandbox.write("prod.env", "A=2\n");
+    assert_success(&sandbox.run(&["add", "--name", "prod", "--source", "prod.env"]));
+
+    let output = sandbox.run(&["list"]);
+    assert_success(&output);
+    assert!(stdout(&output).contains("* dev\n* prod\n"));
+    assert!(stdout(&sandbox.run(&["show", "--name", "dev"])).contains("A=1"));
+
+    assert_code(
+        &sandbox.run_with_editor(&["add", "--name", "dev"], "A=3"),
+        7,
+    );
+    assert_code(
+        &sandbox.run_with_editor(&["add", "--name", "broken"], "NOT VALID"),
+        8,
+    );
+    assert_code(
+        &sandbox.run_with_editor(&["add", "--name", "empty"], "# Please add new env values"),
+        8,
+    );
+
+    assert_success(&sandbox.run(&["remove", "--name", "dev"]));
+    assert!(!stdout(&sandbox.run(&["list"])).contains("* dev"));
+    assert_code(&sandbox.run(&["remove", "--name", "dev"]), 3);
+    assert_code(&sandbox.run(&["show", "--name", "dev"]), 3);
+}
+
+#[test]
+fn test_invalid_env_is_rejected() {
+    let sandbox = Sandbox::init("test_invalid_env_is_rejected");
+    let output = sandbox.run_with_editor(&["add", "--name", "dev"], "BROKEN VALUE\n");
+    assert_code(&output, 8);
+    assert!(stderr(&output).contains("line 1, column 8"));
+
+    sandbox.write("broken.env", "A=1\nBROKEN VALUE\n");
+    assert_code(
+        &sandbox.run(&["add", "--name", "dev", "--source", "broken.env"]),
+        8,
+    );
+    assert!(!stdout(&sandbox.run(&["list"])).contains("* dev"));
+
+    sandbox.add("dev", "A=1\n");
+    assert_code(
+        &sandbox.run_with_editor(&["edit", "--name", "dev"], "BROKEN VALUE\n"),
+        8,
+    );
+    assert_eq!(stdout(&sandbox.run(&["get", "--name", "dev", "A"])), "1\n");
+}
+
+#[test]
+fn test_apply_and_current() {
+    let sandbox = Sandbox::init("test_apply_and_current");
+    sandbox.add("dev", "A=1\n");
+    assert_code(&sandbox.run(&["current"]), 9);
+
+    assert_code(&sandbox.run(&["apply", "--name", "dev"]), 11);
+    assert_success(&sandbox.run(&["apply", "--name", "dev", "--yes"]));
+    assert!(is_symlink(&sandbox.path(".env")));
+    assert_eq!(sandbox.read(".env"), "A=1\n");
+    assert!(stdout(&sandbox.run(&["current"])).contains("The current profile is dev"));
+
+    assert_success(&sandbox.run(&["apply", "--name", "dev", "--yes", "--mode", "copy"]));
+    assert!(!is_symlink(&sandbox.path(".env")));
+    assert_eq!(sandbox.read(".env"), "A=1\n");
+
+    assert_code(&sandbox.run(&["apply", "--name", "missing", "--yes"]), 3);
+    assert_code(
+        &sandbox.run(&["apply", "--name", "dev", "--mode", "move"]),
+        1,
+    );
+}
+
+#[test]
+fn test_hardlink_follows_changes() {
+    let sandbox = Sandbox::init("test_hardlink_follows_changes");
+    sandbox.add("dev", "A=1\n");
+    assert_success(&sandbox.run(&["apply", "--name", "dev", "--yes", "--mode", "hardlink"]));
+
+    assert_success(&sandbox.run(&["set", "--name", "dev", "A=2"]));
+    assert_eq!(sandbox.read(".env"), "A=2\n");
+    assert_eq!(
+        stdout(&sandbox.run(&["status", "--porcelain"])),
+        "applied dev\n"
+    );
+
+    // Editing .env edits the profile through the link
+    fs::OpenOptions::new()
+        .write(true)
+        .truncate(true)
+        .open(sandbox.path(".env"))
+        .unwrap()
+        .write_all(b"A=local\n")
+        .unwrap();
+    assert_eq!(
+        stdout(&sandbox.run(&["status", "--porcelain"])),
+        "modified dev\n"
+    );
+    assert_code(&sandbox.run(&["show", "--name", "dev"]), 12);
+}
+
+#[test]
+fn test_apply_from_subdirectory_and_with_project_dir() {
+    let sandbox = Sandbox::init("test_apply_from_subdirectory");
+    sandbox.add("dev", "A=1\n");
+    fs::create_dir_all(sandbox.path("src/deep")).unwrap();
+
+    let output = sandbox
+        .command(&["apply", "--name", "dev", "--yes"])
+        .current_dir(sandbox.path("src/deep"))
+        .output()
+        .unwrap();
+    assert_success(&output);
+    assert_eq!(sandbox.read(".env"), "A=1\n");
+    assert!(!sandbox.path("src/deep/.env").exists());
+
+    let project = sandbox.project.to_str().unwrap();
+    let output = sandbox
+        .command(&["-C", project, "list"])
+        .current_dir("/")
+        .output()
+        .unwrap();
+    assert_success(&output);
+    assert!(stdout(&output).contains("* dev"));
+}
+
+#[test]
+fn test_encrypt_from_subdirectory() {
+    let sandbox = Sandbox::init("test_encrypt_from_subdirectory");
+    sandbox.add("dev", "A=1\n");
+    fs::create_dir_all(sandbox.path("src")).unwrap();
+
+    let output = sandbox
+        .command(&["init", "--encrypt"])
+        .current_dir(sandbox.path("src"))
+        .env("RPILOT_PASSPHRASE", "secret")
+        .output()
+        .unwrap();
+    assert_code(&output, 7);
+    assert!(!sandbox.path("src/.rpilot").exists());
+    assert!(stdout(&sandbox.run(&["show", "--name", "dev"])).contains("A=1\n"));
+}
+
+#[test]
+fn test_relative_data_dir() {
+    let sandbox = Sandbox::init("test_relative_data_dir");
+    sandbox.add("dev", "A=1\n");
+    fs::create_dir_all(sandbox.path("src")).unwrap();
+
+    let output = sandbox
+        .command(&["apply", "--name", "dev", "--yes"])
+        .current_dir(sandbox.path("src"))
+        .env("RPILOT_HOME", "../../data")
+        .output()
+        .unwrap();
+    assert_success(&output);
+    assert!(fs::read_link(sandbox.path(".env")).unwrap().is_absolute());
+
+    let project = sandbox.project.to_str().unwrap();
+    let output = sandbox
+        .command(&["-C", project, "status", "--porcelain"])
+        .current_dir(sandbox.project.parent().unwrap())
+        .env("RPILOT_HOME", "data")
+        .output()
+        .unwrap();
+    assert_eq!(stdout(&output), "applied dev\n");
+}
+
+#[test]
+fn test_backup_and_restore() {
+    let sandbox = Sandbox::init("test_backup_and_restore");
+    sandbox.add("dev", "A=1\n");
+    sandbox.write(".env", "LOCAL=1\n");
+    assert_code(&sandbox.run(&["restore-backup"]), 3);
+
+    assert_success(&sandbox.run(&["apply", "--name", "dev", "--yes"]));
+    assert_eq!(sandbox.read(".env"), "A=1\n");
+    assert!(stdout(&sandbox.run(&["restore-backup", "--list"])).contains("* "));
+
+    assert_success(&sandbox.run(&["restore-backup"]));
+    assert_eq!(sandbox.read(".env"), "LOCAL=1\n");
+}
+
+#[test]
+fn test_edit_and_status() {
+    let sandbox = Sandbox::init("test_edit_and_status");
+    sandbox.add("dev", "A=1\n");
+    assert_eq!(
+        stdout(&sandbox.run(&["status", "--porcelain"])),
+        "missing\n"
+    );
+
+    assert_success(&sandbox.run(&["apply", "--name", "dev", "--yes", "--mode", "copy"]));
+    assert_eq!(
+        stdout(&sandbox.run(&["status", "--porcelain"])),
+        "applied dev\n"
+    );
+
+    assert_success(&sandbox.run_with_editor(&["edit", "--name", "dev"], "A=2\n"));
+    assert_eq!(sandbox.read(".env"), "A=2\n");
+
+    sandbox.write(".env", "A=local\n");
+    assert_eq!(
+        stdout(&sandbox.run(&["status", "--porcelain"])),
+        "modified dev\n"
+    );
+    assert_success(&sandbox.run_with_editor(&["edit", "--name", "dev"], "A=3\n"));
+    assert_eq!(sandbox.read(".env"), "A=local\n");
+    assert_code(&sandbox.run_with_editor(&["edit", "--name", "x"], "A=1"), 3);
+}
+
+#[test]
+fn test_get_set_and_unset() {
+    let sandbox = Sandbox::init("test_get_set_and_unset");
+    sandbox.add("dev", "A=1\n");
+
+    assert_success(&sandbox.run(&["set", "--name", "dev", "B=two words", "C=3"]));
+    assert_eq!(
+        stdout(&sandbox.run(&["get", "--name", "dev", "B"])),
+        "two words\n"
+    );
+    assert_success(&sandbox.run(&["unset", "--name", "dev", "C"]));
+    assert_code(&sandbox.run(&["get", "--name", "dev", "C"]), 3);
+    assert_code(&sandbox.run(&["set", "--name", "dev", "NOT-VALID"]), 1);
+}
+
+#[test]
+fn test_protect_and_config() {
+    let sandbox = Sandbox::init("test_protect_and_config");
+    sandbox.add("prod", "A=1\n");
+    assert_success(&sandbox.run(&["protect", "--name", "prod"]));
+    assert!(stdout(&sandbox.run(&["list"])).contains("* prod (protected)"));
+    assert_code(&sandbox.run(&["apply", "--name", "prod", "--yes"]), 11);
+    assert!(!sandbox.path(".env").exists());
+
+    assert_success(&sandbox.run(&["protect", "--name", "prod", "--off"]));
+    assert_success(&sandbox.run(&["config", "auto-confirm", "true"]));
+    assert_eq!(stdout(&sandbox.run(&["config", "auto-confirm"])), "true\n");
+    assert_success(&sandbox.run(&["apply", "--name", "prod"]));
+    assert_code(&sandbox.run(&["config", "unknown", "1"]), 1);
+}
+
+#[test]
+fn test_run() {
+    let sandbox = Sandbox::init("test_run");
+    sandbox.add("dev", "GREETING=hello\n");
+
+    let output = sandbox.run(&["run", "--name", "dev", "--", "sh", "-c", "echo $GREETING"]);
+    assert_success(&output);
+    assert_eq!(stdout(&output), "hello\n");
+    assert!(!sandbox.path(".env").exists());
+
+    assert_code(
+        &sandbox.run(&["run", "--name", "dev", "--", "sh", "-c", "exit 42"]),
+        42,
+    );
+    assert_code(
+        &sandbox.run(&["run", "--name", "dev", "--", "rpilot-test-missing"]),
+        127,
+    );
+
+    assert_success(&sandbox.run(&["config", "interpolation", "profile"]));
+    assert_success(&sandbox.run(&["set", "--name", "dev", "MESSAGE=${GREETING} world"]));
+    let output = sandbox.run(&["run", "--name", "dev", "--", "sh", "-c", "echo $MESSAGE"]);
+    assert_eq!(stdout(&output), "hello world\n");
+}
+
+#[test]
+fn test_rename_clone_and_diff() {
+    let sandbox = Sandbox::init("test_rename_clone_and_diff");
+    sandbox.add("dev", "A=1\nB=2\n");
+    assert_success(&sandbox.run(&["rename", "dev", "develop"]));
+    assert_code(&sandbox.run(&["show", "--name", "dev"]), 3);
+    assert_success(&sandbox.run(&["clone", "develop", "staging"]));
+    assert_code(&sandbox.run(&["clone", "develop", "staging"]), 7);
+
+    assert_success(&sandbox.run(&["set", "--name", "staging", "B=3"]));
+    let output = sandbox.run(&["diff", "develop", "staging", "--show-values"]);
+    assert_success(&output);
+    assert!(stdout(&output).contains("-B=2\n+B=3"));
+}
+
+#[test]
+fn test_clone_keeps_protection_and_bases() {
+    let sandbox = Sandbox::init("test_clone_keeps_protection_and_bases");
+    sandbox.add("common", "A=1\n");
+    sandbox.write("prod.env", "B=2\n");
+    assert_success(&sandbox.run(&[
+        "add", "--name", "prod", "--source", "prod.env", "--base", "common",
+    ]));
+    assert_success(&sandbox.run(&["protect", "--name", "prod"]));
+
+    assert_success(&sandbox.run(&["clone", "prod", "prod2"]));
+    assert!(stdout(&sandbox.run(&["list"])).contains("* prod2 (protected)"));
+    let output = sandbox.run(&["show", "--name", "prod2", "--resolved"]);
+    assert!(stdout(&output).contains("A=1 # from common\nB=2 # from prod2\n"));
+}
+
+#[test]
+fn test_inheritance() {
+    let sandbox = Sandbox::init("test_inheritance");
+    sandbox.add("common", "A=1\nB=1\n");
+    sandbox.write("dev.env", "B=2\n");
+    assert_success(&sandbox.run(&[
+        "add", "--name", "dev", "--source", "dev.env", "--base", "common",
+    ]));
+
+    let output = sandbox.run(&["show", "--name", "dev", "--resolved"]);
+    assert!(stdout(&output).contains("A=1 # from common\nB=2 # from dev\n"));
+    assert_success(&sandbox.run(&["apply", "--name", "dev", "--yes"]));
+    assert_eq!(sandbox.read(".env"), "A=1\nB=2\n");
+
+    assert_code(
+        &sandbox.run(&["edit", "--name", "common", "--base", "dev"]),
+        8,
+    );
+    assert_code(&sandbox.run(&["remove", "--name", "common"]), 1);
+    assert_code(
+        &sandbox.run(&["edit", "--name", "dev", "--base", "common", "--no-bases"]),
+        1,
+    );
+
+    let files = fs::read_dir(sandbox.project_data()).unwrap().count();
+    assert_code(
+        &sandbox.run(&[
+            "add", "--name", "prod", "--source", "dev.env", "--base", "missing",
+        ]),
+        3,
+    );
+    assert_eq!(fs::read_dir(sandbox.project_data()).unwrap().count(), files);
+}
+
+#[test]
+fn test_check_and_example() {
+    let sandbox = Sandbox::init("test_check_and_example");
+    sandbox.add("dev", "# database\nPORT=80\n");
+    sandbox.add("prod", "PORT=http\n");
+    sandbox.write(".env.schema", "PORT=port\n");
+
+    assert_success(&sandbox.run(&["check", "--name", "dev"]));
+    assert_code(&sandbox.run(&["check", "--all"]), 13);
+    assert_code(&sandbox.run(&["apply", "--name", "prod", "--yes"]), 13);
+    assert_success(&sandbox.run(&["apply", "--name", "prod", "--yes", "--no-check"]));
+
+    let output = sandbox.run(&["example", "--name", "dev"]);
+    assert_success(&output);
+    assert_eq!(stdout(&output), "# database\nPORT=\n");
+}
+
+#[test]
+fn test_export() {
+    let sandbox = Sandbox::init("test_export");
+    sandbox.add("dev", "A=it's\n");
+
+    let output = sandbox.run(&["export", "--name", "dev", "--shell", "bash"]);
+    assert_success(&output);
+    assert_eq!(stdout(&output), "export A='it'\\''s';\n");
+    let output = sandbox.run(&["export", "--name", "dev", "--shell", "fish", "--unset"]);
+    assert_eq!(stdout(&output), "set -e A;\n");
+    assert_code(
+        &sandbox.run(&["export", "--name", "dev", "--shell", "cmd"]),
+        1,
+    );
+}
+
+#[test]
+fn test_hook_needs_allow() {
+    let sandbox = Sandbox::init("test_hook_needs_allow");
+    sandbox.add("dev", "A=1\n");
+    assert_success(&sandbox.run(&["apply", "--name", "dev", "--yes"]));
+
+    let output = sandbox
+        .command(&["hook", "bash", "--eval"])
+        .env_remove("LOG")
+        .output()
+        .unwrap();
+    assert_success(&output);
+    assert_eq!(stdout(&output), "");
+    assert!(stderr(&output).contains("Run `rpilot allow`"));
+
+    assert_success(&sandbox.run(&["allow"]));
+    let output = sandbox
+        .command(&["hook", "bash", "--eval"])
+        .env_remove("LOG")
+        .output()
+        .unwrap();
+    assert!(stdout(&output).starts_with("export A='1';\nexport RPILOT_HOOK_STATE="));
+    assert_eq!(stderr(&output), "");
+    assert_code(&sandbox.run(&["hook", "sh"]), 1);
+
+    // Moving from another project into this one, whose profile can not be loaded
+    assert_success(&sandbox.run(&["config", "interpolation", "profile"]));
+    assert_success(&sandbox.run(&["set", "--name", "dev", "B=${MISSING}"]));
+    let output = sandbox
+        .command(&["hook", "bash", "--eval"])
+        .env(
+            "RPILOT_HOOK_STATE",
+            r#"{"dir":"/other","profile":"prod","previous":{"SECRET_A":null}}"#,
+        )
+        .output()
+        .unwrap();
+    assert_code(&output, 8);
+    assert_eq!(
+        stdout(&output),
+        "unset SECRET_A;\nunset RPILOT_HOOK_STATE;\n"
+    );
+}
+
+#[test]
+fn test_verify_detects_tampering() {
+    let sandbox = Sandbox::init("test_verify_detects_tampering");
+    sandbox.add("dev", "A=1\n");
+    assert_success(&sandbox.run(&["verify"]));
+
+    let profile = fs::read_dir(sandbox.project_data())
+        .unwrap()
+        .map(|v| v.unwrap().path())
+        .find(|v| v.file_name().unwrap().len() == 36)
+        .unwrap();
+    fs::write(&profile, "A=evil\n").unwrap();
+    assert_code(&sandbox.run(&["verify"]), 12);
+    assert_code(&sandbox.run(&["apply", "--name", "dev", "--yes"]), 12);
+
+    assert_code(&sandbox.run(&["verify", "--accept"]), 0);
+    assert_success(&sandbox.run(&["apply", "--name", "dev", "--yes"]));
+}
+
+#[test]
+fn test_hmac_key_is_kept_out_of_the_data_dir() {
+    let sandbox = Sandbox::init("test_hmac_key_is_kept_out_of_the_data_dir");
+    sandbox.add("dev", "A=1\n");
+    assert_success(&sandbox.run(&["verify", "--hmac"]));
+    let id = fs::read_to_string(sandbox.path(".rpilot")).unwrap();
+    let key = sandbox
+        .data
+        .with_file_name("keys")
+        .join(format!("{}.key", id));
+    assert_eq!(
+        fs::metadata(&key).unwrap().permissions().mode() & 0o777,
+        0o600
+    );
+    assert_success(&sandbox.run(&["show", "--name", "dev"]));
+
+    // Whoever can write to the data dir can not forge the hash of a profile
+    let config_path = sandbox.project_data().join("config");
+    let mut config: serde_json::Value =
+        serde_json::from_slice(&fs::read(&config_path).unwrap()).unwrap();
+    assert!(config.get("hmac_key").is_none());
+    let profile = config["entries"][0]["id"].as_str().unwrap().to_string();
+    fs::write(sandbox.project_data().join(profile), "A=evil\n").unwrap();
+    let hash = ring::digest::digest(&ring::digest::SHA256, b"A=evil\n");
+    config["entries"][0]["hash"] = base64::encode(hash.as_ref()).into();
+    fs::write(&config_path, config.to_string()).unwrap();
+    assert_code(&sandbox.run(&["show", "--name", "dev"]), 12);
+}
+
+#[test]
+fn test_encryption() {
+    let sandbox = Sandbox::new("test_encryption");
+    let output = sandbox
+        .command(&["init", "--encrypt"])
+        .env("RPILOT_PASSPHRASE", "secret")
+        .output()
+        .unwrap();
+    assert_success(&output);
+
+    let output = sandbox
+        .command(&["set", "--name", "dev", "A=1"])
+        .env("RPILOT_PASSPHRASE", "secret")
+        .output()
+        .unwrap();
+    assert_code(&output, 3);
+    sandbox.write("dev.env", "A=1\n");
+    let output = sandbox
+        .command(&["add", "--name", "dev", "--source", "dev.env"])
+        .env("RPILOT_PASSPHRASE", "secret")
+        .output()
+        .unwrap();
+    assert_success(&output);
+
+    let output = sandbox
+        .command(&["get", "--name", "dev", "A"])
+        .env("RPILOT_PASSPHRASE", "wrong")
+        .output()
+        .unwrap();
+    assert_code(&output, 10);
+    let output = sandbox
+        .command(&["get", "--name", "dev", "A"])
+        .env("RPILOT_PASSPHRASE", "secret")
+        .output()
+        .unwrap();
+    assert_eq!(stdout(&output), "1\n");
+}
+
+#[test]
+fn test_backups_follow_the_passphrase() {
+    let sandbox = Sandbox::init("test_backups_follow_the_passphrase");
+    sandbox.add("dev", "A=1\n");
+    sandbox.write(".env", "LOCAL=secret\n");
+    assert_success(&sandbox.run(&["apply", "--name", "dev", "--yes"]));
+
+    let output = sandbox
+        .command(&["init", "--encrypt"])
+        .env("RPILOT_PASSPHRASE", "first")
+        .output()
+        .unwrap();
+    assert_success(&output);
+    let output = sandbox
+        .command(&["rotate-key"])
+        .env("RPILOT_PASSPHRASE", "first")
+        .env("RPILOT_NEW_PASSPHRASE", "second")
+        .output()
+        .unwrap();
+    assert_success(&output);
+
+    let backups = sandbox.project_data().join("backups");
+    for entry in fs::read_dir(backups).unwrap() {
+        let content = fs::read(entry.unwrap().path()).unwrap();
+        assert!(!String::from_utf8_lossy(&content).contains("LOCAL=secret"));
+    }
+    let output = sandbox
+        .command(&["restore-backup"])
+        .env("RPILOT_PASSPHRASE", "second")
+        .output()
+        .unwrap();
+    assert_success(&output);
+    assert_eq!(sandbox.read(".env"), "LOCAL=secret\n");
+}
+
+#[test]
+fn test_gc() {
+    let sandbox = Sandbox::init("test_gc");
+    sandbox.add("dev", "A=1\n");
+    let orphan = sandbox
+        .project_data()
+        .join("00000000-0000-0000-0000-000000000000");
+    fs::write(&orphan, "A=1\n").unwrap();
+
+    assert_code(&sandbox.run(&["gc"]), 11);
+    assert_success(&sandbox.run(&["gc", "--dry-run"]));
+    assert!(orphan.exists());
+    assert_success(&sandbox.run(&["gc", "--yes"]));
+    assert!(!orphan.exists());
+    assert!(stdout(&sandbox.run(&["list"])).contains("* dev"));
+}
+
+#[test]
+fn test_history_and_rollback() {
+    let sandbox = Sandbox::init("test_history_and_rollback");
+    sandbox.add("prod", "A=1\n");
+    assert!(stdout(&sandbox.run(&["history", "--name", "prod"])).contains("no history"));
+
+    assert_success(&sandbox.run_with_editor(
+        &["edit", "--name", "prod", "--message", "bad edit"],
+        "A=broken\n",
+    ));
+    assert_success(&sandbox.run(&["set", "--name", "prod", "B=2"]));
+    let output = sandbox.run(&["history", "--name", "prod"]);
+    assert_success(&output);
+    let lines = stdout(&output)
+        .lines()
+        .skip(1)
+        .map(String::from)
+        .collect::<Vec<_>>();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].starts_with("* 2 "));
+    assert!(lines[1].starts_with("* 1 ") && lines[1].ends_with(" bad edit"));
+
+    let output = sandbox.run(&["diff", "--name", "prod", "--rev", "1", "--show-values"]);
+    assert_success(&output);
+    assert_eq!(
+        stdout(&output),
+        "--- prod revision 1\n+++ prod\n-A=1\n+A=broken\n+B=2\n"
+    );
+    assert_code(&sandbox.run(&["diff", "--name", "prod", "--rev", "9"]), 3);
+
+    assert_success(&sandbox.run(&["rollback", "--name", "prod", "--to", "1"]));
+    assert_eq!(stdout(&sandbox.run(&["get", "--name", "prod", "A"])), "1\n");
+    assert_code(&sandbox.run(&["get", "--name", "prod", "B"]), 3);
+    let output = sandbox.run(&["history", "--name", "prod"]);
+    assert!(stdout(&output).contains(" rollback to 1\n"));
+    assert_code(
+        &sandbox.run(&["rollback", "--name", "prod", "--to", "9"]),
+        3,
+    );
+    assert_code(&sandbox.run(&["rollback", "--name", "dev", "--to", "1"]), 3);
+
+    assert_success(&sandbox.run(&["config", "history-limit", "1"]));
+    let output = sandbox.run(&["history", "--name", "prod"]);
+    assert_eq!(stdout(&output).lines().count(), 2);
+    assert_code(
+        &sandbox.run(&["rollback", "--name", "prod", "--to", "1"]),
+        3,
+    );
+}
+
+#[test]
+fn test_history_is_encrypted_and_verified() {
+    let sandbox = Sandbox::new("test_history_is_encrypted_and_verified");
+    let run = |args: &[&str]| {
+        sandbox
+            .command(args)
+            .env("RPILOT_PASSPHRASE", "secret")
+            .env("RPILOT_NEW_PASSPHRASE", "rotated")
+            .output()
+            .unwrap()
+    };
+    assert_success(&sandbox.run(&["init"]));
+    sandbox.add("prod", "TOKEN=plain\n");
+    assert_success(&sandbox.run(&["set", "--name", "prod", "TOKEN=new"]));
+    assert_success(&run(&["init", "--encrypt"]));
+
+    let history_dir = sandbox.project_data().join("history");
+    let revision = fs::read_dir(&history_dir)
+        .unwrap()
+        .next()
+        .unwrap()
+        .unwrap()
+        .path()
+        .join("1");
+    assert!(!fs::read_to_string(&revision)
+        .unwrap_or_default()
+        .contains("plain"));
+
+    assert_success(&run(&["rotate-key"]));
+    let output = sandbox
+        .command(&["rollback", "--name", "prod", "--to", "1"])
+        .env("RPILOT_PASSPHRASE", "rotated")
+        .output()
+        .unwrap();
+    assert_success(&output);
+
+    fs::write(&revision, "TOKEN=evil\n").unwrap();
+    let output = sandbox
+        .command(&["verify"])
+        .env("RPILOT_PASSPHRASE", "rotated")
+        .output()
+        .unwrap();
+    assert_code(&output, 12);
+    assert!(stdout(&output).contains("* prod revision 1: modified outside rpilot"));
+}